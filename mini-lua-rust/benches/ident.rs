@@ -0,0 +1,18 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use mini_lua_rust::Lexer;
+
+// Identifier-heavy input for the `is_name_char` fast path: run with `cargo bench --bench ident`.
+fn main() {
+    let source = "local alpha_beta, gamma_delta = epsilon_zeta(eta_theta, iota_kappa) or lambda_mu\n".repeat(20_000);
+    let mut best = f64::INFINITY;
+    for _ in 0..20 {
+        let start = Instant::now();
+        let mut lexer = Lexer::new(black_box(source.clone()));
+        lexer.scan_tokens().unwrap();
+        black_box(&lexer.token_list);
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    println!("ident: {:.2} ms for {} KiB", best * 1e3, source.len() / 1024);
+}
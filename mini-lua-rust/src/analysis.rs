@@ -0,0 +1,662 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::{LexError, Lexer, LexerOptions, Position, ReservedWord, Span, Symbol, Token, TokenValue};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BracketError {
+    Unmatched { position: Position },
+    Unclosed { position: Position },
+    TooDeep { position: Position },
+}
+
+impl fmt::Display for BracketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BracketError::Unmatched { position } => {
+                write!(f, "{}:{}: Unmatched closing bracket.", position.line, position.column)
+            }
+            BracketError::Unclosed { position } => {
+                write!(f, "{}:{}: Unclosed bracket.", position.line, position.column)
+            }
+            BracketError::TooDeep { position } => {
+                write!(f, "{}:{}: Brackets nested too deeply.", position.line, position.column)
+            }
+        }
+    }
+}
+
+pub fn bracket_depths(tokens: &[Token], max_depth: usize) -> Result<Vec<usize>, BracketError> {
+    let mut depths = Vec::with_capacity(tokens.len());
+    let mut open: Vec<(&Token, Symbol)> = vec![];
+    for token in tokens {
+        let TokenValue::Symbol(symbol) = &token.value else {
+            depths.push(open.len());
+            continue;
+        };
+        match symbol {
+            Symbol::LeftParen | Symbol::LeftBrace | Symbol::LeftBracket => {
+                depths.push(open.len());
+                if open.len() == max_depth {
+                    return Err(BracketError::TooDeep { position: token.position });
+                }
+                open.push((token, closing_symbol(*symbol).unwrap()));
+            }
+            Symbol::RightParen | Symbol::RightBrace | Symbol::RightBracket => {
+                match open.pop() {
+                    Some((_, close)) if close == *symbol => depths.push(open.len()),
+                    _ => return Err(BracketError::Unmatched { position: token.position }),
+                }
+            }
+            _ => depths.push(open.len()),
+        }
+    }
+    if let Some((token, _)) = open.pop() {
+        return Err(BracketError::Unclosed { position: token.position });
+    }
+    Ok(depths)
+}
+
+pub fn matching_bracket(tokens: &[Token], open_index: usize) -> Option<usize> {
+    let TokenValue::Symbol(symbol) = tokens.get(open_index)?.value else {
+        return None;
+    };
+    let mut open = vec![closing_symbol(symbol)?];
+    for (index, token) in tokens.iter().enumerate().skip(open_index + 1) {
+        let TokenValue::Symbol(symbol) = token.value else {
+            continue;
+        };
+        match symbol {
+            Symbol::LeftParen | Symbol::LeftBrace | Symbol::LeftBracket => open.push(closing_symbol(symbol)?),
+            Symbol::RightParen | Symbol::RightBrace | Symbol::RightBracket => {
+                if open.pop() != Some(symbol) {
+                    return None;
+                }
+                if open.is_empty() {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn closing_symbol(open: Symbol) -> Option<Symbol> {
+    match open {
+        Symbol::LeftParen => Some(Symbol::RightParen),
+        Symbol::LeftBrace => Some(Symbol::RightBrace),
+        Symbol::LeftBracket => Some(Symbol::RightBracket),
+        _ => None,
+    }
+}
+
+pub fn are_adjacent(a: Span, b: Span) -> bool {
+    a.end == b.start
+}
+
+// Tokens are moved rather than rebuilt, so each keeps the position the lexer gave it.
+pub fn without_eol(mut tokens: Vec<Token>) -> Vec<Token> {
+    tokens.retain(|token| !matches!(token.value, TokenValue::EOL));
+    tokens
+}
+
+pub fn fold_paths(tokens: Vec<Token>) -> Vec<Token> {
+    let mut folded: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let TokenValue::Name(name) = &token.value else {
+            folded.push(token);
+            continue;
+        };
+        let mut names = vec![name.clone()];
+        let mut span = token.span;
+        let mut dot = None;
+        while let Some(next) = tokens.next_if(|next| matches!(next.value, TokenValue::Symbol(Symbol::Dot))) {
+            match tokens.next_if(|next| matches!(next.value, TokenValue::Name(_))) {
+                Some(Token { value: TokenValue::Name(name), span: name_span, .. }) => {
+                    names.push(name);
+                    span = span.join(name_span);
+                }
+                _ => {
+                    dot = Some(next);
+                    break;
+                }
+            }
+        }
+        if names.len() == 1 {
+            folded.push(token);
+        } else {
+            folded.push(Token { value: TokenValue::Path(names), span, position: token.position });
+        }
+        folded.extend(dot);
+    }
+    folded
+}
+
+pub fn redundant_semicolons(tokens: &[Token]) -> Vec<Position> {
+    let mut positions = vec![];
+    let mut previous: Option<&TokenValue> = None;
+    for token in tokens {
+        match &token.value {
+            TokenValue::EOL => continue,
+            TokenValue::Symbol(Symbol::Semicolon)
+                if matches!(previous, None | Some(TokenValue::Symbol(Symbol::Semicolon))) =>
+            {
+                positions.push(token.position);
+            }
+            _ => {}
+        }
+        previous = Some(&token.value);
+    }
+    positions
+}
+
+// Matches `function NAME {. NAME} : NAME`, also accepting a folded path in place of the dotted names.
+pub fn method_definitions(tokens: &[Token]) -> Vec<Span> {
+    let mut colons = vec![];
+    let mut tokens = tokens.iter().filter(|token| !matches!(token.value, TokenValue::EOL)).peekable();
+    while let Some(token) = tokens.next() {
+        if !matches!(token.value, TokenValue::Reserved(ReservedWord::Function)) {
+            continue;
+        }
+        if tokens.next_if(|next| matches!(next.value, TokenValue::Name(_) | TokenValue::Path(_))).is_none() {
+            continue;
+        }
+        while tokens.next_if(|next| matches!(next.value, TokenValue::Symbol(Symbol::Dot))).is_some() {
+            if tokens.next_if(|next| matches!(next.value, TokenValue::Name(_))).is_none() {
+                break;
+            }
+        }
+        if let Some(colon) = tokens.next_if(|next| matches!(next.value, TokenValue::Symbol(Symbol::Colon)))
+            && tokens.peek().is_some_and(|next| matches!(next.value, TokenValue::Name(_)))
+        {
+            colons.push(colon.span);
+        }
+    }
+    colons
+}
+
+pub fn first_token_columns(tokens: &[Token]) -> Vec<(usize, usize)> {
+    let mut columns: Vec<(usize, usize)> = vec![];
+    for token in tokens {
+        if matches!(token.value, TokenValue::EOL | TokenValue::EOF) {
+            continue;
+        }
+        if columns.last().is_none_or(|&(line, _)| line < token.position.line) {
+            columns.push((token.position.line, token.position.column));
+        }
+    }
+    columns
+}
+
+#[derive(Default)]
+pub struct BlockTracker {
+    pub depth: isize,
+    loop_headers: usize,
+}
+
+impl BlockTracker {
+    pub fn update(&mut self, value: &TokenValue) {
+        match value {
+            TokenValue::Reserved(ReservedWord::Function | ReservedWord::If | ReservedWord::Repeat) => self.depth += 1,
+            TokenValue::Reserved(ReservedWord::While | ReservedWord::For) => {
+                self.depth += 1;
+                self.loop_headers += 1;
+            }
+            TokenValue::Reserved(ReservedWord::Do) if self.loop_headers > 0 => self.loop_headers -= 1,
+            TokenValue::Reserved(ReservedWord::Do) => self.depth += 1,
+            TokenValue::Reserved(ReservedWord::End | ReservedWord::Until) => self.depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockBalanceError {
+    Unexpected { word: ReservedWord, position: Position },
+    Unclosed { word: ReservedWord, position: Position },
+}
+
+impl fmt::Display for BlockBalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockBalanceError::Unexpected { word, position } => {
+                write!(f, "{}:{}: Unexpected '{}'.", position.line, position.column, word.as_str())
+            }
+            BlockBalanceError::Unclosed { word, position } => {
+                write!(f, "{}:{}: '{}' is never closed.", position.line, position.column, word.as_str())
+            }
+        }
+    }
+}
+
+pub fn check_block_balance(tokens: &[Token]) -> Result<(), BlockBalanceError> {
+    match block_balance_errors(tokens).into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+pub fn validate(source: &str) -> Result<Vec<BlockBalanceError>, LexError> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.scan_tokens()?;
+    Ok(block_balance_errors(&lexer.token_list))
+}
+
+fn block_balance_errors(tokens: &[Token]) -> Vec<BlockBalanceError> {
+    let mut errors = vec![];
+    let mut open: Vec<(ReservedWord, Position)> = vec![];
+    let mut loop_headers = 0;
+    for token in tokens {
+        let TokenValue::Reserved(word) = token.value else {
+            continue;
+        };
+        match word {
+            ReservedWord::Function | ReservedWord::If | ReservedWord::Repeat => open.push((word, token.position)),
+            ReservedWord::While | ReservedWord::For => {
+                open.push((word, token.position));
+                loop_headers += 1;
+            }
+            ReservedWord::Do if loop_headers > 0 => loop_headers -= 1,
+            ReservedWord::Do => open.push((word, token.position)),
+            ReservedWord::End | ReservedWord::Until => {
+                // `repeat` is closed by `until`; every other block by `end`. A stray closer is
+                // reported and skipped, leaving the open block for its real closer.
+                match open.last() {
+                    Some(&(opener, _)) if (opener == ReservedWord::Repeat) == (word == ReservedWord::Until) => {
+                        open.pop();
+                    }
+                    _ => errors.push(BlockBalanceError::Unexpected { word, position: token.position }),
+                }
+            }
+            _ => {}
+        }
+    }
+    errors.extend(open.into_iter().rev().map(|(word, position)| BlockBalanceError::Unclosed { word, position }));
+    errors
+}
+
+pub fn indent_levels(tokens: &[Token]) -> Vec<usize> {
+    let mut levels = vec![];
+    let mut blocks = BlockTracker::default();
+    let mut braces: isize = 0;
+    for token in tokens {
+        while levels.len() < token.position.line && !matches!(token.value, TokenValue::EOF) {
+            let dedent = levels.len() + 1 == token.position.line && closes_block(&token.value);
+            let depth = blocks.depth + braces - dedent as isize;
+            levels.push(depth.max(0) as usize);
+        }
+        blocks.update(&token.value);
+        match token.value {
+            TokenValue::Symbol(Symbol::LeftBrace) => braces += 1,
+            TokenValue::Symbol(Symbol::RightBrace) => braces -= 1,
+            _ => {}
+        }
+    }
+    levels
+}
+
+fn closes_block(value: &TokenValue) -> bool {
+    matches!(
+        value,
+        TokenValue::Reserved(ReservedWord::End | ReservedWord::Until | ReservedWord::Else | ReservedWord::ElseIf)
+            | TokenValue::Symbol(Symbol::RightBrace)
+    )
+}
+
+pub fn split_statements(tokens: &[Token]) -> Vec<Range<usize>> {
+    let mut statements = vec![];
+    let mut blocks = BlockTracker::default();
+    let mut brackets = 0isize;
+    let mut start = None;
+    let mut previous: Option<(usize, &TokenValue)> = None;
+    for (index, token) in tokens.iter().enumerate() {
+        let value = &token.value;
+        let top_level = blocks.depth == 0 && brackets == 0;
+        match value {
+            TokenValue::EOL | TokenValue::EOF => continue,
+            TokenValue::Symbol(Symbol::Semicolon) if top_level => {
+                if let (Some(first), Some((last, _))) = (start.take(), previous) {
+                    statements.push(first..last + 1);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        // Without a parser, a new statement is assumed wherever one could end and another begin.
+        if top_level
+            && let (Some(first), Some((last, previous))) = (start, previous)
+            && ends_expression(previous)
+            && starts_statement(value)
+        {
+            statements.push(first..last + 1);
+            start = None;
+        }
+        start.get_or_insert(index);
+        blocks.update(value);
+        match value {
+            TokenValue::Symbol(Symbol::LeftParen | Symbol::LeftBrace | Symbol::LeftBracket) => brackets += 1,
+            TokenValue::Symbol(Symbol::RightParen | Symbol::RightBrace | Symbol::RightBracket) => brackets -= 1,
+            _ => {}
+        }
+        previous = Some((index, value));
+    }
+    if let (Some(first), Some((last, _))) = (start, previous) {
+        statements.push(first..last + 1);
+    }
+    statements
+}
+
+fn ends_expression(value: &TokenValue) -> bool {
+    matches!(
+        value,
+        TokenValue::Name(_)
+            | TokenValue::Path(_)
+            | TokenValue::Number(_)
+            | TokenValue::String(_)
+            | TokenValue::ByteString(_)
+            | TokenValue::Symbol(Symbol::RightParen | Symbol::RightBracket | Symbol::RightBrace | Symbol::Ellipsis)
+            | TokenValue::Reserved(
+                ReservedWord::End | ReservedWord::True | ReservedWord::False | ReservedWord::Nil | ReservedWord::Break
+            )
+    )
+}
+
+fn starts_statement(value: &TokenValue) -> bool {
+    matches!(
+        value,
+        TokenValue::Name(_)
+            | TokenValue::Path(_)
+            | TokenValue::Reserved(
+                ReservedWord::Local
+                    | ReservedWord::Function
+                    | ReservedWord::If
+                    | ReservedWord::While
+                    | ReservedWord::For
+                    | ReservedWord::Repeat
+                    | ReservedWord::Return
+                    | ReservedWord::Break
+                    | ReservedWord::Do
+            )
+    )
+}
+
+pub fn extract_strings(source: &str) -> Result<Vec<(String, Span)>, LexError> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.scan_tokens()?;
+    let strings = lexer.token_list.into_iter().filter_map(|token| match token.value {
+        TokenValue::String(string) => Some((string, token.span)),
+        TokenValue::ByteString(bytes) => Some((String::from_utf8_lossy(&bytes).into_owned(), token.span)),
+        _ => None,
+    });
+    Ok(strings.collect())
+}
+
+pub fn identifiers(source: &str) -> Result<Vec<(String, Span)>, LexError> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.scan_tokens()?;
+    let names = lexer.token_list.into_iter().filter_map(|token| match token.value {
+        TokenValue::Name(name) => Some((name, token.span)),
+        _ => None,
+    });
+    Ok(names.collect())
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LocStats {
+    pub total: usize,
+    pub blank: usize,
+    pub comment: usize,
+    pub code: usize,
+}
+
+pub fn loc(source: &str) -> Result<LocStats, LexError> {
+    let options = LexerOptions { keep_comments: true, ..LexerOptions::default() };
+    let mut lexer = Lexer::with_options(source.to_string(), options);
+    lexer.scan_tokens()?;
+    let total = source.lines().count();
+    let mut code = vec![false; total + 1];
+    let mut comment = vec![false; total + 1];
+    for token in &lexer.token_list {
+        let lines = match token.value {
+            TokenValue::EOL | TokenValue::EOF => continue,
+            TokenValue::Comment(..) => &mut comment,
+            _ => &mut code,
+        };
+        let last = token.position.line + lexer.span_text(token.span).matches('\n').count();
+        lines[token.position.line..=last.min(total)].fill(true);
+    }
+    let mut stats = LocStats { total, ..LocStats::default() };
+    for line in 1..=total {
+        if code[line] {
+            stats.code += 1;
+        } else if comment[line] {
+            stats.comment += 1;
+        } else {
+            stats.blank += 1;
+        }
+    }
+    Ok(stats)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TokenDiff<'a> {
+    Changed { index: usize, left: &'a Token, right: &'a Token },
+    Removed { index: usize, left: &'a Token },
+    Added { index: usize, right: &'a Token },
+}
+
+pub fn diff_tokens<'a>(left: &'a [Token], right: &'a [Token]) -> Vec<TokenDiff<'a>> {
+    let mut diffs = vec![];
+    for index in 0..left.len().max(right.len()) {
+        match (left.get(index), right.get(index)) {
+            (Some(left), Some(right)) if left != right => diffs.push(TokenDiff::Changed { index, left, right }),
+            (Some(left), None) => diffs.push(TokenDiff::Removed { index, left }),
+            (None, Some(right)) => diffs.push(TokenDiff::Added { index, right }),
+            _ => {}
+        }
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex_strict;
+
+    fn lex(source: &str) -> Vec<Token> {
+        lex_strict(source).unwrap()
+    }
+
+    fn names(tokens: &[Token]) -> Vec<&str> {
+        tokens
+            .iter()
+            .filter_map(|token| match &token.value {
+                TokenValue::Name(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bracket_depths_track_nesting() {
+        assert_eq!(bracket_depths(&lex("a{(b)[c]}"), 8).unwrap(), [0, 0, 1, 2, 1, 1, 2, 1, 0, 0]);
+        let error = bracket_depths(&lex("{{{}}}"), 2).unwrap_err();
+        assert_eq!(error, BracketError::TooDeep { position: Position { line: 1, column: 3 } });
+        assert_eq!(error.to_string(), "1:3: Brackets nested too deeply.");
+        let error = bracket_depths(&lex("(]"), 8).unwrap_err();
+        assert_eq!(error, BracketError::Unmatched { position: Position { line: 1, column: 2 } });
+        assert_eq!(error.to_string(), "1:2: Unmatched closing bracket.");
+        let error = bracket_depths(&lex("(\n{"), 8).unwrap_err();
+        assert_eq!(error, BracketError::Unclosed { position: Position { line: 2, column: 1 } });
+        assert_eq!(error.to_string(), "2:1: Unclosed bracket.");
+    }
+
+    #[test]
+    fn bracket_depths_handle_deep_nesting() {
+        let source = format!("{}{}", "{".repeat(100_000), "}".repeat(100_000));
+        let depths = bracket_depths(&lex(&source), usize::MAX).unwrap();
+        assert_eq!(depths[99_999], 99_999);
+        assert_eq!(depths[100_000], 99_999);
+    }
+
+    #[test]
+    fn redundant_semicolons_are_found() {
+        assert_eq!(
+            redundant_semicolons(&lex(";a = 1;;\n;b()")),
+            [
+                Position { line: 1, column: 1 },
+                Position { line: 1, column: 8 },
+                Position { line: 2, column: 1 },
+            ]
+        );
+        assert!(redundant_semicolons(&lex("a = 1; b = 2;")).is_empty());
+    }
+
+    #[test]
+    fn first_token_columns_skip_blank_lines() {
+        assert_eq!(first_token_columns(&lex("a\n  b c\n\n\tif")), [(1, 1), (2, 3), (4, 2)]);
+    }
+
+    #[test]
+    fn split_statements_without_a_parser() {
+        let tokens = lex("local a = 1 b = f(a,\n2); if a then c() end\nreturn");
+        let statements: Vec<Vec<&str>> =
+            split_statements(&tokens).into_iter().map(|range| names(&tokens[range])).collect();
+        assert_eq!(statements, [vec!["a"], vec!["b", "f", "a"], vec!["a", "c"], vec![]]);
+        assert_eq!(split_statements(&tokens)[3], 22..23);
+    }
+
+    #[test]
+    fn extract_strings_decodes_literals() {
+        let strings = extract_strings("print('a\\n', [[b]], \"\\xFF\")").unwrap();
+        assert_eq!(
+            strings,
+            [
+                ("a\n".to_string(), Span { start: 6, end: 11 }),
+                ("b".to_string(), Span { start: 13, end: 18 }),
+                ("\u{FFFD}".to_string(), Span { start: 20, end: 26 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_bracket_pairs_openers() {
+        let tokens = lex("f(a[1], {b}) x");
+        assert_eq!(matching_bracket(&tokens, 1), Some(10));
+        assert_eq!(matching_bracket(&tokens, 3), Some(5));
+        assert_eq!(matching_bracket(&tokens, 7), Some(9));
+        assert_eq!(matching_bracket(&tokens, 0), None);
+        assert_eq!(matching_bracket(&tokens, 10), None);
+        assert_eq!(matching_bracket(&lex("(]"), 0), None);
+        assert_eq!(matching_bracket(&lex("(("), 0), None);
+    }
+
+    #[test]
+    fn loc_counts_code_comment_and_blank_lines() {
+        let source = "-- header\n\nlocal s = [[\ntext\n]] -- trailing\n--[[ block\n]]\n  \nx = 1\n";
+        assert_eq!(loc(source).unwrap(), LocStats { total: 9, blank: 2, comment: 3, code: 4 });
+    }
+
+    #[test]
+    fn indent_levels_follow_blocks_and_braces() {
+        let source = "if x then\nt = {\n1,\n}\nelse\ny()\nend\n";
+        assert_eq!(indent_levels(&lex(source)), [0, 1, 2, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn diff_tokens_compares_by_index() {
+        let left = lex("a = 1");
+        let right = lex("a = 2 b");
+        let diffs = diff_tokens(&left, &right);
+        assert_eq!(
+            diffs,
+            [
+                TokenDiff::Changed { index: 2, left: &left[2], right: &right[2] },
+                TokenDiff::Changed { index: 3, left: &left[3], right: &right[3] },
+                TokenDiff::Added { index: 4, right: &right[4] },
+            ]
+        );
+        assert_eq!(diff_tokens(&right, &left).last(), Some(&TokenDiff::Removed { index: 4, left: &right[4] }));
+        assert!(diff_tokens(&left, &left).is_empty());
+    }
+
+    #[test]
+    fn adjacency_compares_span_ends() {
+        let tokens = lex("f(x) ,y");
+        assert!(are_adjacent(tokens[0].span, tokens[1].span));
+        assert!(!are_adjacent(tokens[3].span, tokens[4].span));
+        assert!(are_adjacent(tokens[4].span, tokens[5].span));
+    }
+
+    #[test]
+    fn block_balance_reports_the_first_problem() {
+        assert_eq!(check_block_balance(&lex("while x do if y then end end")), Ok(()));
+        assert_eq!(check_block_balance(&lex("repeat x = 1 until x")), Ok(()));
+        let error = check_block_balance(&lex("function f()\n  if x then\nend")).unwrap_err();
+        let position = Position { line: 1, column: 1 };
+        assert_eq!(error, BlockBalanceError::Unclosed { word: ReservedWord::Function, position });
+        assert_eq!(error.to_string(), "1:1: 'function' is never closed.");
+        let error = check_block_balance(&lex("do end end")).unwrap_err();
+        assert_eq!(error.to_string(), "1:8: Unexpected 'end'.");
+    }
+
+    #[test]
+    fn fold_paths_merges_dotted_names() {
+        let tokens = fold_paths(lex("a.b.c:d(x.) y"));
+        let path = TokenValue::Path(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(tokens[0].value, path);
+        assert_eq!(tokens[0].span, Span { start: 0, end: 5 });
+        assert_eq!(tokens[1].value, TokenValue::Symbol(Symbol::Colon));
+        assert_eq!(tokens[4].value, TokenValue::Name("x".to_string()));
+        assert_eq!(tokens[5].value, TokenValue::Symbol(Symbol::Dot));
+        assert_eq!(tokens[7].value, TokenValue::Name("y".to_string()));
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        assert_eq!(validate("for i = 1, 2 do end").unwrap(), []);
+        assert_eq!(
+            validate("until\nfunction f()\nrepeat\nend").unwrap(),
+            [
+                BlockBalanceError::Unexpected { word: ReservedWord::Until, position: Position { line: 1, column: 1 } },
+                BlockBalanceError::Unexpected { word: ReservedWord::End, position: Position { line: 4, column: 1 } },
+                BlockBalanceError::Unclosed { word: ReservedWord::Repeat, position: Position { line: 3, column: 1 } },
+                BlockBalanceError::Unclosed { word: ReservedWord::Function, position: Position { line: 2, column: 1 } },
+            ]
+        );
+        assert_eq!(validate("'open").unwrap_err().kind, crate::LexErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn without_eol_keeps_positions() {
+        let tokens = without_eol(lex("a\n\nb\n"));
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1].value, TokenValue::Name("b".to_string()));
+        assert_eq!(tokens[1].position, Position { line: 3, column: 1 });
+        assert_eq!(tokens[2].value, TokenValue::EOF);
+    }
+
+    #[test]
+    fn identifiers_keep_duplicates_in_order() {
+        assert_eq!(
+            identifiers("local x = y + x").unwrap(),
+            [
+                ("x".to_string(), Span { start: 6, end: 7 }),
+                ("y".to_string(), Span { start: 10, end: 11 }),
+                ("x".to_string(), Span { start: 14, end: 15 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn method_definitions_find_header_colons() {
+        let tokens = lex("function obj:greet() end");
+        assert_eq!(method_definitions(&tokens), [Span { start: 12, end: 13 }]);
+        let tokens = lex("function a.b.c:d() end\nlocal f = function() end\nx:y()\nfunction\ng:h() end");
+        let colons = method_definitions(&tokens);
+        assert_eq!(colons, [Span { start: 14, end: 15 }, Span { start: 64, end: 65 }]);
+        assert_eq!(method_definitions(&fold_paths(tokens)), colons);
+    }
+}
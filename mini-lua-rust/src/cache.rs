@@ -0,0 +1,285 @@
+use std::fmt;
+
+use crate::{CommentKind, NumberLiteral, NumberValue, Position, ReservedWord, Span, Symbol, Token, TokenValue};
+
+const RESERVED: [ReservedWord; 22] = [
+    ReservedWord::And,
+    ReservedWord::Break,
+    ReservedWord::Do,
+    ReservedWord::Else,
+    ReservedWord::ElseIf,
+    ReservedWord::End,
+    ReservedWord::False,
+    ReservedWord::For,
+    ReservedWord::Function,
+    ReservedWord::Goto,
+    ReservedWord::If,
+    ReservedWord::In,
+    ReservedWord::Local,
+    ReservedWord::Nil,
+    ReservedWord::Not,
+    ReservedWord::Or,
+    ReservedWord::Repeat,
+    ReservedWord::Return,
+    ReservedWord::Then,
+    ReservedWord::True,
+    ReservedWord::Until,
+    ReservedWord::While,
+];
+
+const SYMBOLS: [Symbol; 33] = [
+    Symbol::Add,
+    Symbol::Minus,
+    Symbol::Multiply,
+    Symbol::Divide,
+    Symbol::FloorDivide,
+    Symbol::Mod,
+    Symbol::Power,
+    Symbol::Length,
+    Symbol::Equal,
+    Symbol::GreaterEqual,
+    Symbol::LessEqual,
+    Symbol::Less,
+    Symbol::Greater,
+    Symbol::NotEqual,
+    Symbol::LeftParen,
+    Symbol::RightParen,
+    Symbol::LeftBrace,
+    Symbol::RightBrace,
+    Symbol::LeftBracket,
+    Symbol::RightBracket,
+    Symbol::Semicolon,
+    Symbol::Colon,
+    Symbol::DoubleColon,
+    Symbol::Comma,
+    Symbol::Dot,
+    Symbol::Concat,
+    Symbol::Ellipsis,
+    Symbol::Assign,
+    Symbol::BitAnd,
+    Symbol::BitOr,
+    Symbol::BitXor,
+    Symbol::ShiftLeft,
+    Symbol::ShiftRight,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeError {
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid token cache at byte {}.", self.offset)
+    }
+}
+
+pub fn to_bytes(tokens: &[Token]) -> Vec<u8> {
+    let mut bytes = vec![];
+    put_u64(&mut bytes, tokens.len() as u64);
+    for token in tokens {
+        match &token.value {
+            TokenValue::Reserved(word) => bytes.extend([0, *word as u8]),
+            TokenValue::CustomReserved(word) => {
+                bytes.push(1);
+                put_bytes(&mut bytes, word.as_bytes());
+            },
+            TokenValue::Number(number) => {
+                match number.value {
+                    NumberValue::Int(value) => {
+                        bytes.push(2);
+                        bytes.extend(value.to_le_bytes());
+                    },
+                    NumberValue::Float(value) => {
+                        bytes.push(3);
+                        bytes.extend(value.to_bits().to_le_bytes());
+                    },
+                    NumberValue::Unparsed => bytes.push(14),
+                }
+                put_bytes(&mut bytes, number.lexeme.as_bytes());
+            },
+            TokenValue::String(string) => {
+                bytes.push(4);
+                put_bytes(&mut bytes, string.as_bytes());
+            },
+            TokenValue::ByteString(string) => {
+                bytes.push(5);
+                put_bytes(&mut bytes, string);
+            },
+            TokenValue::Symbol(symbol) => bytes.extend([6, *symbol as u8]),
+            TokenValue::Name(name) => {
+                bytes.push(7);
+                put_bytes(&mut bytes, name.as_bytes());
+            },
+            TokenValue::Comment(CommentKind::Line, text) => {
+                bytes.push(8);
+                put_bytes(&mut bytes, text.as_bytes());
+            },
+            TokenValue::Comment(CommentKind::Block { level }, text) => {
+                bytes.push(9);
+                put_u64(&mut bytes, *level as u64);
+                put_bytes(&mut bytes, text.as_bytes());
+            },
+            TokenValue::EOL => bytes.push(10),
+            TokenValue::EOF => bytes.push(11),
+            TokenValue::Directive(text) => {
+                bytes.push(12);
+                put_bytes(&mut bytes, text.as_bytes());
+            },
+            TokenValue::Path(names) => {
+                bytes.push(13);
+                put_u64(&mut bytes, names.len() as u64);
+                for name in names {
+                    put_bytes(&mut bytes, name.as_bytes());
+                }
+            },
+        }
+        put_u64(&mut bytes, token.span.start as u64);
+        put_u64(&mut bytes, token.span.end as u64);
+        put_u64(&mut bytes, token.position.line as u64);
+        put_u64(&mut bytes, token.position.column as u64);
+    }
+    bytes
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Vec<Token>, DecodeError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let count = reader.usize()?;
+    let mut tokens = vec![];
+    for _ in 0..count {
+        let tag_offset = reader.offset;
+        let value = match reader.byte()? {
+            0 => {
+                let tag = reader.byte()?;
+                let word = RESERVED.into_iter().find(|&word| word as u8 == tag);
+                TokenValue::Reserved(word.ok_or(DecodeError { offset: tag_offset + 1 })?)
+            },
+            1 => TokenValue::CustomReserved(reader.string()?),
+            2 => {
+                let value = NumberValue::Int(i64::from_le_bytes(reader.array()?));
+                TokenValue::Number(NumberLiteral { value, lexeme: reader.string()? })
+            },
+            3 => {
+                let value = NumberValue::Float(f64::from_bits(u64::from_le_bytes(reader.array()?)));
+                TokenValue::Number(NumberLiteral { value, lexeme: reader.string()? })
+            },
+            4 => TokenValue::String(reader.string()?),
+            5 => TokenValue::ByteString(reader.bytes()?.to_vec()),
+            6 => {
+                let tag = reader.byte()?;
+                let symbol = SYMBOLS.into_iter().find(|&symbol| symbol as u8 == tag);
+                TokenValue::Symbol(symbol.ok_or(DecodeError { offset: tag_offset + 1 })?)
+            },
+            7 => TokenValue::Name(reader.string()?),
+            8 => TokenValue::Comment(CommentKind::Line, reader.string()?),
+            9 => {
+                let level = reader.usize()?;
+                TokenValue::Comment(CommentKind::Block { level }, reader.string()?)
+            },
+            10 => TokenValue::EOL,
+            11 => TokenValue::EOF,
+            12 => TokenValue::Directive(reader.string()?),
+            13 => {
+                let count = reader.usize()?;
+                TokenValue::Path((0..count).map(|_| reader.string()).collect::<Result<_, _>>()?)
+            },
+            14 => TokenValue::Number(NumberLiteral { value: NumberValue::Unparsed, lexeme: reader.string()? }),
+            _ => return Err(DecodeError { offset: tag_offset }),
+        };
+        let span = Span { start: reader.usize()?, end: reader.usize()? };
+        let position = Position { line: reader.usize()?, column: reader.usize()? };
+        tokens.push(Token { value, span, position });
+    }
+    if reader.offset != bytes.len() {
+        return Err(DecodeError { offset: reader.offset });
+    }
+    Ok(tokens)
+}
+
+fn put_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend(value.to_le_bytes());
+}
+
+fn put_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    put_u64(bytes, data.len() as u64);
+    bytes.extend_from_slice(data);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let length = self.usize()?;
+        self.take(length)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let offset = self.offset;
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| DecodeError { offset })
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        let offset = self.offset;
+        usize::try_from(u64::from_le_bytes(self.array()?)).map_err(|_| DecodeError { offset })
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.offset.checked_add(length).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or(DecodeError { offset: self.offset })?;
+        let data = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::fold_paths;
+    use crate::{Lexer, LexerOptions, LuaVersion};
+
+    #[test]
+    fn tokens_round_trip() {
+        let options = LexerOptions {
+            version: LuaVersion::Lua53,
+            keep_comments: true,
+            directives: true,
+            extra_keywords: vec!["continue".to_string()],
+            ..LexerOptions::default()
+        };
+        let source = "$line\nlocal a.b = 1 + 2.5 ~ 0x10 -- c\n--[=[ d ]=] s = '\\xFF' .. \"é\" continue\n";
+        let mut lexer = Lexer::with_options(source.to_string(), options);
+        lexer.scan_tokens().unwrap();
+        let tokens = fold_paths(lexer.token_list);
+        assert_eq!(from_bytes(&to_bytes(&tokens)).unwrap(), tokens);
+
+        let options = LexerOptions { exact_numbers: true, ..LexerOptions::default() };
+        let mut lexer = Lexer::with_options("1.000000000000000000001".to_string(), options);
+        lexer.scan_tokens().unwrap();
+        assert_eq!(from_bytes(&to_bytes(&lexer.token_list)).unwrap(), lexer.token_list);
+    }
+
+    #[test]
+    fn invalid_caches_are_rejected() {
+        let mut lexer = Lexer::new("x = 1".to_string());
+        lexer.scan_tokens().unwrap();
+        let bytes = to_bytes(&lexer.token_list);
+        assert_eq!(from_bytes(&bytes[..bytes.len() - 1]), Err(DecodeError { offset: bytes.len() - 8 }));
+        assert_eq!(from_bytes(&[bytes.as_slice(), &[0]].concat()), Err(DecodeError { offset: bytes.len() }));
+        let mut bad_tag = bytes.clone();
+        bad_tag[8] = 99;
+        assert_eq!(from_bytes(&bad_tag), Err(DecodeError { offset: 8 }));
+        assert_eq!(DecodeError { offset: 8 }.to_string(), "Invalid token cache at byte 8.");
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{CommentKind, LexError, Lexer, LexerOptions, LuaVersion, NumberValue, Token, TokenValue};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HexDigitCase {
+    Lower,
+    Upper,
+}
+
+pub fn normalize_number_lexeme(lexeme: &str, case: HexDigitCase) -> String {
+    let Some(digits) = lexeme.strip_prefix("0x").or_else(|| lexeme.strip_prefix("0X")) else {
+        return lexeme.replace('E', "e");
+    };
+    let mut normalized = String::from("0x");
+    let mut exponent = false;
+    for ch in digits.chars() {
+        if ch == 'p' || ch == 'P' {
+            exponent = true;
+            normalized.push('p');
+        } else if exponent {
+            normalized.push(ch);
+        } else {
+            match case {
+                HexDigitCase::Lower => normalized.push(ch.to_ascii_lowercase()),
+                HexDigitCase::Upper => normalized.push(ch.to_ascii_uppercase()),
+            }
+        }
+    }
+    normalized
+}
+
+pub fn normalize_numbers(tokens: &mut [Token], version: LuaVersion) {
+    for token in tokens {
+        let TokenValue::Number(number) = &mut token.value else {
+            continue;
+        };
+        match number.value {
+            // A negative integer is a wrapped hex or binary literal; `-1` would read back as two tokens.
+            NumberValue::Int(value) if value >= 0 => number.lexeme = value.to_string(),
+            NumberValue::Float(value) if value.is_finite() => {
+                number.lexeme = value.to_string();
+                // Under 5.3 `1000` would read back as an integer, so keep the float visibly a float.
+                if version >= LuaVersion::Lua53 && !number.lexeme.contains('.') {
+                    number.lexeme.push_str(".0");
+                }
+            }
+            NumberValue::Int(_) | NumberValue::Float(_) | NumberValue::Unparsed => {}
+        }
+    }
+}
+
+pub fn strip_comments(source: &str) -> Result<String, LexError> {
+    let options = LexerOptions { keep_comments: true, ..LexerOptions::default() };
+    let mut lexer = Lexer::with_options(source.to_string(), options);
+    lexer.scan_tokens()?;
+    let mut stripped = String::with_capacity(source.len());
+    let mut copied = 0;
+    for token in &lexer.token_list {
+        if let TokenValue::Comment(..) = token.value {
+            stripped.push_str(&source[copied..token.span.start]);
+            copied = token.span.end;
+        }
+    }
+    stripped.push_str(&source[copied..]);
+    Ok(stripped)
+}
+
+pub fn to_source(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    let mut previous: Option<&Token> = None;
+    for token in tokens {
+        if let Some(previous) = previous {
+            if let TokenValue::Comment(CommentKind::Line, _) | TokenValue::Directive(_) = previous.value {
+                if !matches!(token.value, TokenValue::EOL | TokenValue::EOF) {
+                    source.push('\n');
+                }
+            } else if needs_separator(previous, token) {
+                source.push(' ');
+            }
+        }
+        source.push_str(&spelling(token));
+        previous = Some(token);
+    }
+    source
+}
+
+pub fn needs_separator(prev: &Token, next: &Token) -> bool {
+    if is_word(prev) && is_word(next) {
+        return true;
+    }
+    let (Some(last), Some(first)) = (spelling(prev).chars().last(), spelling(next).chars().next()) else {
+        return false;
+    };
+    match (last, first) {
+        // `1..2` is a malformed number and `.` `5` would read back as `.5`.
+        (_, '.') if matches!(prev.value, TokenValue::Number(_)) => true,
+        ('.', '.' | '0'..='9') => true,
+        // `- -x` joined would start a comment.
+        ('-', '-') => true,
+        ('=' | '<' | '>' | '~', '=') | ('<', '<') | ('>', '>') | ('/', '/') | (':', ':') => true,
+        ('[', '[' | '=') => true,
+        _ => false,
+    }
+}
+
+fn spelling(token: &Token) -> String {
+    match &token.value {
+        TokenValue::Reserved(word) => word.as_str().to_string(),
+        TokenValue::CustomReserved(word) | TokenValue::Name(word) => word.clone(),
+        TokenValue::Number(number) => number.lexeme.clone(),
+        TokenValue::String(string) => quote(string.as_bytes()),
+        TokenValue::ByteString(string) => quote(string),
+        TokenValue::Symbol(symbol) => symbol.as_str().to_string(),
+        TokenValue::Path(names) => names.join("."),
+        TokenValue::Comment(CommentKind::Line, text) => format!("--{text}"),
+        TokenValue::Comment(CommentKind::Block { level }, text) => {
+            let equals = "=".repeat(*level);
+            // The lexer drops a newline right after the opening bracket, so one in the text needs another.
+            let newline = if text.starts_with('\n') { "\n" } else { "" };
+            format!("--[{equals}[{newline}{text}]{equals}]")
+        }
+        TokenValue::Directive(text) => format!("${text}"),
+        TokenValue::EOL => "\n".to_string(),
+        TokenValue::EOF => String::new(),
+    }
+}
+
+fn is_word(token: &Token) -> bool {
+    matches!(
+        token.value,
+        TokenValue::Reserved(_)
+            | TokenValue::CustomReserved(_)
+            | TokenValue::Name(_)
+            | TokenValue::Path(_)
+            | TokenValue::Number(_)
+    )
+}
+
+fn quote(string: &[u8]) -> String {
+    let mut quoted = String::from('"');
+    for chunk in string.utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\0'..='\x1F' | '\x7F' => quoted.push_str(&format!("\\{:03}", ch as u32)),
+                ch => quoted.push(ch),
+            }
+        }
+        for byte in chunk.invalid() {
+            quoted.push_str(&format!("\\{byte:03}"));
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenKind;
+
+    fn lex_with(source: &str, options: LexerOptions) -> Vec<Token> {
+        let mut lexer = Lexer::with_options(source.to_string(), options);
+        lexer.scan_tokens().unwrap();
+        lexer.token_list
+    }
+
+    fn number_lexemes(tokens: &[Token]) -> Vec<&str> {
+        tokens
+            .iter()
+            .filter_map(|token| match &token.value {
+                TokenValue::Number(number) => Some(number.lexeme.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn kinds(tokens: &[Token]) -> Vec<TokenKind> {
+        tokens.iter().map(Token::kind).collect()
+    }
+
+    fn values(tokens: &[Token]) -> Vec<TokenValue> {
+        tokens.iter().map(|token| token.value.clone()).collect()
+    }
+
+    #[test]
+    fn number_lexemes_get_one_spelling() {
+        assert_eq!(normalize_number_lexeme("0XaBp+4", HexDigitCase::Lower), "0xabp+4");
+        assert_eq!(normalize_number_lexeme("0xaB.cP-1", HexDigitCase::Upper), "0xAB.Cp-1");
+        assert_eq!(normalize_number_lexeme("1E5", HexDigitCase::Upper), "1e5");
+    }
+
+    #[test]
+    fn strip_comments_keeps_everything_else() {
+        let source = "x = 1 -- one\n--[[ block\n]]y = '--'\n";
+        assert_eq!(strip_comments(source).unwrap(), "x = 1 \ny = '--'\n");
+        assert_eq!(strip_comments("--[[").unwrap_err().kind, crate::LexErrorKind::UnterminatedLongComment);
+    }
+
+    #[test]
+    fn normalize_numbers_rewrites_lexemes_as_decimal() {
+        let cases = [(LuaVersion::Lua51, ["16", "1000", "0.5"]), (LuaVersion::Lua53, ["16", "1000.0", "0.5"])];
+        for (version, expected) in cases {
+            let mut tokens = lex_with("0x10 1e3 .5 x", LexerOptions { version, ..LexerOptions::default() });
+            normalize_numbers(&mut tokens, version);
+            assert_eq!(number_lexemes(&tokens), expected);
+        }
+    }
+
+    #[test]
+    fn normalize_numbers_keeps_wrapped_hex_integers() {
+        let options = LexerOptions { version: LuaVersion::Lua53, ..LexerOptions::default() };
+        let mut tokens = lex_with("0xFFFFFFFFFFFFFFFF 0x10", options);
+        normalize_numbers(&mut tokens, LuaVersion::Lua53);
+        assert_eq!(number_lexemes(&tokens), ["0xFFFFFFFFFFFFFFFF", "16"]);
+    }
+
+    #[test]
+    fn to_source_keeps_directives() {
+        let options = LexerOptions { directives: true, ..LexerOptions::default() };
+        let tokens = lex_with("$debug\nx = 1", options.clone());
+        let source = to_source(&tokens);
+        assert_eq!(source, "$debug\nx=1");
+        assert_eq!(kinds(&lex_with(&source, options)), kinds(&tokens));
+    }
+
+    #[test]
+    fn to_source_round_trips_the_fixture() {
+        let tokens = lex_with(include_str!("../test_code.lua"), LexerOptions::default());
+        let source = to_source(&tokens);
+        assert_eq!(values(&lex_with(&source, LexerOptions::default())), values(&tokens));
+    }
+
+    #[test]
+    fn to_source_keeps_tokens_apart() {
+        let options = LexerOptions { keep_comments: true, ..LexerOptions::default() };
+        for source in [
+            "a - -b",
+            "x = 1 .. 2",
+            "a . .5",
+            "a = b == c",
+            "t[ [[s]] ]",
+            "s = [[\nline]]",
+            "s = '\\0\\r\\255\\\"'",
+            "f() -- note\ng()",
+            "--[==[ long ]] ]==] x",
+            "local function f(...) return ... end",
+        ] {
+            let tokens = lex_with(source, options.clone());
+            let emitted = to_source(&tokens);
+            assert_eq!(values(&lex_with(&emitted, options.clone())), values(&tokens), "{source:?} -> {emitted:?}");
+        }
+        assert_eq!(to_source(&lex_with("local x = f ( a , b )", options)), "local x=f(a,b)");
+    }
+
+    #[test]
+    fn needs_separator_flags_ambiguous_pairs() {
+        let options = LexerOptions { version: LuaVersion::Lua53, ..LexerOptions::default() };
+        let pairs = [
+            ("a b", true),
+            ("1 x", true),
+            ("a (", false),
+            ("- -", true),
+            ("- 1", false),
+            ("1 ..", true),
+            (". .5", true),
+            (".. .", true),
+            ("< =", true),
+            ("< <", true),
+            ("/ /", true),
+            ("[ [", true),
+            ("[ =", true),
+            (") (", false),
+        ];
+        for (source, expected) in pairs {
+            let tokens = lex_with(source, options.clone());
+            assert_eq!(needs_separator(&tokens[0], &tokens[1]), expected, "{source:?}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{LexError, Lexer, LexerOptions, Position, Span, Token, TokenValue};
+
+pub struct Edit {
+    pub offset: usize,
+    pub deleted: usize,
+    pub inserted: String,
+}
+
+// `tokens` must come from a lexer built with `options`; the new text is lexed with them too.
+pub fn relex(
+    source: &str,
+    tokens: &[Token],
+    edit: &Edit,
+    options: &LexerOptions,
+) -> Result<(String, Vec<Token>), LexError> {
+    let old_edit_end = edit.offset + edit.deleted;
+    let new_edit_end = edit.offset + edit.inserted.len();
+    let text = [&source[..edit.offset], &edit.inserted, &source[old_edit_end..]].concat();
+
+    // Restart one token before the first one touching the edit, since the edit may merge
+    // that token with its neighbour (e.g. inserting a second `-` after `-` starts a comment).
+    let first = tokens
+        .iter()
+        .position(|token| token.span.end >= edit.offset)
+        .unwrap_or(tokens.len().saturating_sub(1));
+    let restart = first.saturating_sub(1);
+    // An edit before the first token may fall in a dropped comment, so that case starts over.
+    let (base_offset, base_position) = match tokens.get(restart) {
+        Some(token) if token.span.start <= edit.offset => (token.span.start, token.position),
+        _ => (0, Position { line: 1, column: 1 }),
+    };
+
+    let mut relexed = tokens[..restart].to_vec();
+    let mut lexer = Lexer::with_options(text[base_offset..].to_string(), options.clone());
+    let mut old = first;
+    loop {
+        let token = rebase(lexer.next_token()?, base_offset, base_position);
+        // Past the edit the lexer only depends on the text from the token start onward, so
+        // once a token lines up with an old one, every later old token is still valid.
+        // Edits that open or close a long comment simply keep running until they line up.
+        if token.span.start >= new_edit_end {
+            let old_start = token.span.start + old_edit_end - new_edit_end;
+            while old < tokens.len() && tokens[old].span.start < old_start {
+                old += 1;
+            }
+            if let Some(old_token) = tokens.get(old)
+                && old_token.span.start == old_start
+                && old_token.span.end - old_start == token.span.end - token.span.start
+                && old_token.value == token.value
+            {
+                relexed.extend(shift_tail(&tokens[old..], &token));
+                return Ok((text, relexed));
+            }
+        }
+        let at_end = matches!(token.value, TokenValue::EOF);
+        relexed.push(token);
+        if at_end {
+            return Ok((text, relexed));
+        }
+    }
+}
+
+fn rebase(mut token: Token, offset: usize, position: Position) -> Token {
+    token.span.start += offset;
+    token.span.end += offset;
+    if token.position.line == 1 {
+        token.position.column += position.column - 1;
+    }
+    token.position.line += position.line - 1;
+    token
+}
+
+fn shift_tail<'a>(tail: &'a [Token], anchor: &'a Token) -> impl Iterator<Item = Token> + 'a {
+    let old_anchor = &tail[0];
+    let offset_delta = anchor.span.start as isize - old_anchor.span.start as isize;
+    let line_delta = anchor.position.line as isize - old_anchor.position.line as isize;
+    let column_delta = anchor.position.column as isize - old_anchor.position.column as isize;
+    tail.iter().map(move |token| {
+        let mut token = token.clone();
+        if token.position.line == old_anchor.position.line {
+            token.position.column = token.position.column.wrapping_add_signed(column_delta);
+        }
+        token.position.line = token.position.line.wrapping_add_signed(line_delta);
+        token.span = Span {
+            start: token.span.start.wrapping_add_signed(offset_delta),
+            end: token.span.end.wrapping_add_signed(offset_delta),
+        };
+        token
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LuaVersion;
+
+    fn lex_with(source: &str, options: &LexerOptions) -> Vec<Token> {
+        let mut lexer = Lexer::with_options(source.to_string(), options.clone());
+        lexer.scan_tokens().unwrap();
+        lexer.token_list
+    }
+
+    fn check_with(options: &LexerOptions, source: &str, offset: usize, deleted: usize, inserted: &str) {
+        let tokens = lex_with(source, options);
+        let edit = Edit { offset, deleted, inserted: inserted.to_string() };
+        let (text, relexed) = relex(source, &tokens, &edit, options).unwrap();
+        assert_eq!(text, [&source[..offset], inserted, &source[offset + deleted..]].concat());
+        assert_eq!(relexed, lex_with(&text, options), "{source:?} -> {text:?}");
+    }
+
+    fn check(source: &str, offset: usize, deleted: usize, inserted: &str) {
+        check_with(&LexerOptions::default(), source, offset, deleted, inserted);
+    }
+
+    #[test]
+    fn relex_matches_a_full_lex() {
+        let source = "local a = 1\nb = a - 2\nprint(a, b)\n";
+        check(source, 6, 1, "abc");
+        check(source, 11, 0, "\n\n");
+        check(source, 19, 0, "-");
+        check(source, 0, 12, "");
+        check(source, source.len(), 0, "x = 3");
+        check("a = 1\nb = 2 ]]\nc = 3", 6, 0, "--[[");
+        check("--[[ a ]] b = 1\nc = 2 ]]", 7, 2, "");
+        check("x = 1 --[[ a ]] b\nc ]]", 13, 2, "");
+        check("-- a\nb", 0, 0, "x ");
+        check("", 0, 0, "x");
+    }
+
+    #[test]
+    fn relex_reports_errors_in_the_new_text() {
+        let options = LexerOptions::default();
+        let tokens = lex_with("s = 'a'", &options);
+        let edit = Edit { offset: 6, deleted: 1, inserted: String::new() };
+        let error = relex("s = 'a'", &tokens, &edit, &options).unwrap_err();
+        assert_eq!(error.kind, crate::LexErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn relex_uses_the_lexer_options() {
+        let keep_comments = LexerOptions { keep_comments: true, ..LexerOptions::default() };
+        check_with(&keep_comments, "-- note\nx = 1", 3, 0, "long ");
+        check_with(&keep_comments, "x = 1 --[[ a ]] y", 11, 1, "b");
+        let lua53 = LexerOptions { version: LuaVersion::Lua53, ..LexerOptions::default() };
+        check_with(&lua53, "a = b // c", 5, 0, " ");
+    }
+}
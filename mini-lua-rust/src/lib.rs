@@ -0,0 +1,2355 @@
+pub mod analysis;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod format;
+pub mod incremental;
+pub mod repl;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub value: TokenValue,
+    pub span: Span,
+    pub position: Position,
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        self.value.kind()
+    }
+
+    pub fn string_value(&self) -> Option<&[u8]> {
+        match &self.value {
+            TokenValue::String(string) => Some(string.as_bytes()),
+            TokenValue::ByteString(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match &self.value {
+            TokenValue::Number(NumberLiteral { value: NumberValue::Int(value), .. }) => Some(*value),
+            TokenValue::Number(number) => number.integer_value(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match &self.value {
+            TokenValue::Number(number) => number.value.as_f64(),
+            _ => None,
+        }
+    }
+
+    pub fn can_begin_expression(&self) -> bool {
+        match &self.value {
+            TokenValue::Number(_)
+            | TokenValue::String(_)
+            | TokenValue::ByteString(_)
+            | TokenValue::Name(_)
+            | TokenValue::Path(_) => true,
+            TokenValue::Reserved(word) => matches!(
+                word,
+                ReservedWord::Not | ReservedWord::Nil | ReservedWord::True | ReservedWord::False | ReservedWord::Function
+            ),
+            TokenValue::Symbol(symbol) => matches!(
+                symbol,
+                Symbol::LeftParen | Symbol::LeftBrace | Symbol::Minus | Symbol::Length | Symbol::BitXor | Symbol::Ellipsis
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum TokenKind {
+    Reserved,
+    Number,
+    String,
+    Symbol,
+    Name,
+    Comment,
+    Directive,
+    EOL,
+    EOF,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum TokenValue {
+    Reserved(ReservedWord),
+    CustomReserved(String),
+    Number(NumberLiteral),
+    String(String),
+    ByteString(Vec<u8>),
+    Symbol(Symbol),
+    Name(String),
+    Path(Vec<String>),
+    Comment(CommentKind, String),
+    Directive(String),
+    EOL,
+    EOF,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommentKind {
+    Line,
+    Block { level: usize },
+}
+
+impl TokenValue {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            TokenValue::Reserved(_) | TokenValue::CustomReserved(_) => TokenKind::Reserved,
+            TokenValue::Number(_) => TokenKind::Number,
+            TokenValue::String(_) | TokenValue::ByteString(_) => TokenKind::String,
+            TokenValue::Symbol(_) => TokenKind::Symbol,
+            TokenValue::Name(_) | TokenValue::Path(_) => TokenKind::Name,
+            TokenValue::Comment(..) => TokenKind::Comment,
+            TokenValue::Directive(_) => TokenKind::Directive,
+            TokenValue::EOL => TokenKind::EOL,
+            TokenValue::EOF => TokenKind::EOF,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberLiteral {
+    pub value: NumberValue,
+    pub lexeme: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberValue {
+    Int(i64),
+    Float(f64),
+    // Only the lexeme is kept, see `LexerOptions::exact_numbers`.
+    Unparsed,
+}
+
+impl NumberValue {
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            NumberValue::Int(value) => Some(value as f64),
+            NumberValue::Float(value) => Some(value),
+            NumberValue::Unparsed => None,
+        }
+    }
+}
+
+impl NumberLiteral {
+    pub fn hex_prefix(&self) -> Option<&str> {
+        self.lexeme.get(..2).filter(|prefix| prefix.eq_ignore_ascii_case("0x"))
+    }
+
+    pub fn integer_value(&self) -> Option<i64> {
+        let digits = self.lexeme.replace('_', "");
+        if self.hex_prefix().is_some() {
+            i64::from_str_radix(&digits[2..], 16).ok()
+        } else if digits.starts_with("0b") || digits.starts_with("0B") {
+            i64::from_str_radix(&digits[2..], 2).ok()
+        } else {
+            digits.parse().ok()
+        }
+    }
+
+    pub fn exponent_marker(&self) -> Option<char> {
+        let markers: &[char] = if self.hex_prefix().is_some() { &['p', 'P'] } else { &['e', 'E'] };
+        self.lexeme.chars().find(|ch| markers.contains(ch))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReservedWord {
+    And,
+    Break,
+    Do,
+    Else,
+    ElseIf,
+    End,
+    False,
+    For,
+    Function,
+    Goto,
+    If,
+    In,
+    Local,
+    Nil,
+    Not,
+    Or,
+    Repeat,
+    Return,
+    Then,
+    True,
+    Until,
+    While,
+}
+
+impl ReservedWord {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservedWord::And => "and",
+            ReservedWord::Break => "break",
+            ReservedWord::Do => "do",
+            ReservedWord::Else => "else",
+            ReservedWord::ElseIf => "elseif",
+            ReservedWord::End => "end",
+            ReservedWord::False => "false",
+            ReservedWord::For => "for",
+            ReservedWord::Function => "function",
+            ReservedWord::Goto => "goto",
+            ReservedWord::If => "if",
+            ReservedWord::In => "in",
+            ReservedWord::Local => "local",
+            ReservedWord::Nil => "nil",
+            ReservedWord::Not => "not",
+            ReservedWord::Or => "or",
+            ReservedWord::Repeat => "repeat",
+            ReservedWord::Return => "return",
+            ReservedWord::Then => "then",
+            ReservedWord::True => "true",
+            ReservedWord::Until => "until",
+            ReservedWord::While => "while",
+        }
+    }
+}
+
+pub const RESERVED_WORDS: [(&str, ReservedWord); 21] = [
+    ("and", ReservedWord::And),
+    ("break", ReservedWord::Break),
+    ("do", ReservedWord::Do),
+    ("else", ReservedWord::Else),
+    ("elseif", ReservedWord::ElseIf),
+    ("end", ReservedWord::End),
+    ("false", ReservedWord::False),
+    ("for", ReservedWord::For),
+    ("function", ReservedWord::Function),
+    ("if", ReservedWord::If),
+    ("in", ReservedWord::In),
+    ("local", ReservedWord::Local),
+    ("nil", ReservedWord::Nil),
+    ("not", ReservedWord::Not),
+    ("or", ReservedWord::Or),
+    ("repeat", ReservedWord::Repeat),
+    ("return", ReservedWord::Return),
+    ("then", ReservedWord::Then),
+    ("true", ReservedWord::True),
+    ("until", ReservedWord::Until),
+    ("while", ReservedWord::While),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symbol {
+    Add,
+    Minus,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Mod,
+    Power,
+    Length,
+    Equal,
+    GreaterEqual,
+    LessEqual,
+    Less,
+    Greater,
+    NotEqual,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Semicolon,
+    Colon,
+    DoubleColon,
+    Comma,
+    Dot,
+    Concat,
+    Ellipsis,
+    Assign,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Symbol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Symbol::Add => "+",
+            Symbol::Minus => "-",
+            Symbol::Multiply => "*",
+            Symbol::Divide => "/",
+            Symbol::FloorDivide => "//",
+            Symbol::Mod => "%",
+            Symbol::Power => "^",
+            Symbol::Length => "#",
+            Symbol::Equal => "==",
+            Symbol::GreaterEqual => ">=",
+            Symbol::LessEqual => "<=",
+            Symbol::Less => "<",
+            Symbol::Greater => ">",
+            Symbol::NotEqual => "~=",
+            Symbol::LeftParen => "(",
+            Symbol::RightParen => ")",
+            Symbol::LeftBrace => "{",
+            Symbol::RightBrace => "}",
+            Symbol::LeftBracket => "[",
+            Symbol::RightBracket => "]",
+            Symbol::Semicolon => ";",
+            Symbol::Colon => ":",
+            Symbol::DoubleColon => "::",
+            Symbol::Comma => ",",
+            Symbol::Dot => ".",
+            Symbol::Concat => "..",
+            Symbol::Ellipsis => "...",
+            Symbol::Assign => "=",
+            Symbol::BitAnd => "&",
+            Symbol::BitOr => "|",
+            Symbol::BitXor => "~",
+            Symbol::ShiftLeft => "<<",
+            Symbol::ShiftRight => ">>",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn join(self, other: Span) -> Span {
+        Span { start: self.start.min(other.start), end: self.end.max(other.end) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexErrorKind {
+    UnterminatedString,
+    InvalidEscape,
+    MalformedNumber,
+    UnexpectedChar(char),
+    UnterminatedLongBracket,
+    UnterminatedLongComment,
+    UnsupportedOperator(String),
+    InvalidUtf8(u8),
+    TernaryOperator,
+    FloatNotAllowed,
+    StepLimitExceeded,
+    TokenTooLong,
+}
+
+impl LexErrorKind {
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            LexErrorKind::UnexpectedChar(_) | LexErrorKind::UnsupportedOperator(_) | LexErrorKind::TernaryOperator
+        )
+    }
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::UnterminatedString => write!(f, "Unfinished string."),
+            LexErrorKind::InvalidEscape => write!(f, "Invalid escape char."),
+            LexErrorKind::MalformedNumber => write!(f, "Invalid number."),
+            LexErrorKind::UnexpectedChar(ch) => write!(f, "Invalid token '{ch}'."),
+            LexErrorKind::UnterminatedLongBracket => write!(f, "Unfinished long bracket."),
+            LexErrorKind::UnterminatedLongComment => write!(f, "Unfinished long comment."),
+            LexErrorKind::UnsupportedOperator(operator) => write!(f, "'{operator}' requires Lua 5.3."),
+            LexErrorKind::InvalidUtf8(byte) => write!(f, "Invalid UTF-8 byte 0x{byte:02X}."),
+            LexErrorKind::TernaryOperator => {
+                write!(f, "Invalid token '?'. Lua has no ternary operator; use 'a and b or c'.")
+            }
+            LexErrorKind::FloatNotAllowed => write!(f, "Float literals are not allowed."),
+            LexErrorKind::StepLimitExceeded => write!(f, "Step limit exceeded."),
+            LexErrorKind::TokenTooLong => write!(f, "Token too long."),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub position: Position,
+}
+
+impl LexError {
+    pub fn render(&self, source: &str, context: usize) -> String {
+        let lines: Vec<&str> = source.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+        let first = self.position.line.saturating_sub(context).max(1);
+        let last = (self.position.line + context).min(lines.len());
+        let width = last.to_string().len();
+        let mut rendered = format!("{self}\n");
+        for line in first..=last {
+            let text = lines[line - 1];
+            rendered.push_str(&format!("{line:>width$} | {text}\n"));
+            if line == self.position.line {
+                let indent: String = text
+                    .chars()
+                    .take(self.position.column - 1)
+                    .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                    .collect();
+                rendered.push_str(&format!("{:width$} | {indent}^\n", ""));
+            }
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.position.line, self.position.column, self.kind)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub range: (Position, Position),
+    pub severity: Severity,
+    pub message: String,
+    pub code: LexErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LuaVersion {
+    Lua51,
+    Lua52,
+    Lua53,
+}
+
+#[cfg(feature = "lua53")]
+pub const SUPPORTED_LUA_VERSION: LuaVersion = LuaVersion::Lua53;
+
+#[cfg(not(feature = "lua53"))]
+pub const SUPPORTED_LUA_VERSION: LuaVersion = LuaVersion::Lua51;
+
+impl Default for LuaVersion {
+    fn default() -> Self {
+        SUPPORTED_LUA_VERSION
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct LexerOptions {
+    pub version: LuaVersion,
+    pub collect_indentation: bool,
+    pub extra_name_start_chars: Vec<char>,
+    pub extra_name_chars: Vec<char>,
+    pub numeric_separators: bool,
+    pub binary_literals: bool,
+    pub recover: bool,
+    pub extra_keywords: Vec<String>,
+    pub removed_keywords: Vec<String>,
+    pub keep_comments: bool,
+    // Ends the stream with exactly one EOL before EOF, merging trailing blank lines into it.
+    // A trailing comment that is not kept still ends its own line.
+    pub final_eol: bool,
+    pub directives: bool,
+    pub collect_line_endings: bool,
+    pub collect_trailing_whitespace: bool,
+    pub integers_only: bool,
+    pub lenient_escapes: bool,
+    pub exact_numbers: bool,
+    pub max_steps: Option<usize>,
+    pub max_name_length: Option<usize>,
+    pub max_number_length: Option<usize>,
+    pub name_classifier: Option<fn(&str) -> Option<TokenValue>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Indentation {
+    pub line: usize,
+    pub spaces: usize,
+    pub tabs: usize,
+}
+
+pub trait Tokenizer {
+    fn next_token(&mut self) -> Result<Token, LexError>;
+    fn peek_token(&mut self) -> Result<&Token, LexError>;
+    fn position(&self) -> Position;
+}
+
+// Only `\n` ends a line, so a lone `\r` (classic Mac) is whitespace and is not recorded here.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+pub struct Lexer {
+    options: LexerOptions,
+    pub text: String,
+    source: Vec<char>,
+    pub token_list: Vec<Token>,
+    current: usize,
+    start: usize,
+    offset: usize,
+    start_offset: usize,
+    position: Position,
+    start_position: Position,
+    last_end_position: Position,
+    reserved_words: HashMap<String, ReservedWord>,
+    peeked: Option<Token>,
+    pub keyword_positions: Vec<(ReservedWord, Position)>,
+    pub indentation: Vec<Indentation>,
+    pub diagnostics: Vec<Diagnostic>,
+    ended_with_eol: bool,
+    blank_tail_start: usize,
+    pub line_endings: Vec<LineEnding>,
+    pub trailing_whitespace: Vec<(Position, Span)>,
+    at_line_start: bool,
+    steps: usize,
+    line_token_count: usize,
+}
+
+impl Lexer {
+    pub fn new(source: String) -> Self {
+        Self::with_options(source, LexerOptions::default())
+    }
+
+    pub fn with_options(source: String, options: LexerOptions) -> Self {
+        let mut reserved_words: HashMap<String, ReservedWord> =
+            RESERVED_WORDS.into_iter().map(|x| (x.0.to_string(), x.1)).collect();
+        if options.version >= LuaVersion::Lua52 {
+            reserved_words.insert("goto".to_string(), ReservedWord::Goto);
+        }
+        for keyword in &options.removed_keywords {
+            reserved_words.remove(keyword);
+        }
+        let source_chars: Vec<char> = source.chars().collect();
+        let blank_tail = source_chars.iter().rev().take_while(|&&ch| Self::is_lua_whitespace(ch)).count();
+        Self {
+            options,
+            blank_tail_start: source_chars.len() - blank_tail,
+            source: source_chars,
+            text: source,
+            token_list: vec![],
+            current: 0,
+            start: 0,
+            offset: 0,
+            start_offset: 0,
+            position: Position { line: 1, column: 1 },
+            start_position: Position { line: 1, column: 1 },
+            last_end_position: Position { line: 1, column: 1 },
+            reserved_words,
+            peeked: None,
+            keyword_positions: vec![],
+            indentation: vec![],
+            diagnostics: vec![],
+            ended_with_eol: false,
+            line_endings: vec![],
+            trailing_whitespace: vec![],
+            at_line_start: true,
+            steps: 0,
+            line_token_count: 0,
+        }
+    }
+
+    pub fn version(&self) -> LuaVersion {
+        self.options.version
+    }
+
+    pub fn scan_tokens(&mut self) -> Result<(), LexError> {
+        loop {
+            let token = self.next_token()?;
+            let at_end = matches!(token.value, TokenValue::EOF);
+            self.token_list.push(token);
+            if at_end {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Token, LexError> {
+        if let Some(token) = self.peeked.take() {
+            return Ok(token);
+        }
+        loop {
+            self.steps += 1;
+            if self.options.max_steps.is_some_and(|max_steps| self.steps > max_steps) {
+                return Err(LexError { kind: LexErrorKind::StepLimitExceeded, position: self.position });
+            }
+            let mut value = match self.scan_token() {
+                Err(error) if self.options.recover && error.kind.is_recoverable() => {
+                    self.push_diagnostic(error);
+                    continue;
+                }
+                result => result?,
+            };
+            if !matches!(value, TokenValue::Comment(..) | TokenValue::EOF) {
+                self.last_end_position = self.position;
+            }
+            if matches!(value, TokenValue::EOF) && self.options.final_eol && !self.ended_with_eol {
+                value = TokenValue::EOL;
+            }
+            if matches!(value, TokenValue::EOL) && self.options.final_eol && self.current >= self.blank_tail_start {
+                while !self.is_at_end() {
+                    self.advance();
+                }
+            }
+            if self.options.keep_comments || !matches!(value, TokenValue::Comment(..)) {
+                self.ended_with_eol = matches!(value, TokenValue::EOL);
+                match value {
+                    TokenValue::EOL => self.line_token_count = 0,
+                    TokenValue::Comment(..) | TokenValue::EOF => {}
+                    _ => self.line_token_count += 1,
+                }
+                return Ok(Token {
+                    value,
+                    span: Span { start: self.start_offset, end: self.offset },
+                    position: self.start_position,
+                });
+            }
+        }
+    }
+
+    pub fn peek_token(&mut self) -> Result<&Token, LexError> {
+        if self.peeked.is_none() {
+            let token = self.next_token()?;
+            self.peeked = Some(token);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+
+    pub fn dump_tokens(&self, out: &mut impl Write) -> io::Result<()> {
+        for token in &self.token_list {
+            let token_type = match token.value {
+                TokenValue::Reserved(_) | TokenValue::CustomReserved(_) => "[RESERVED]",
+                TokenValue::Number(_) => "[NUMBER]",
+                TokenValue::String(_) | TokenValue::ByteString(_) => "[STRING]",
+                TokenValue::Symbol(_) => "[SYMBOL]",
+                TokenValue::Name(_) | TokenValue::Path(_) => "[NAME]",
+                TokenValue::Comment(..) => "[COMMENT]",
+                TokenValue::Directive(_) => "[DIRECTIVE]",
+                TokenValue::EOL => "[EOL]",
+                TokenValue::EOF => continue,
+            };
+            if matches!(token.value, TokenValue::EOL) {
+                writeln!(out, "{token_type}")?;
+            } else {
+                writeln!(out, "{token_type} {}", self.span_text(token.span))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn dump_json(&self, out: &mut impl Write) -> io::Result<()> {
+        for token in &self.token_list {
+            let lexeme = self.span_text(token.span);
+            let (kind, value) = match &token.value {
+                TokenValue::Reserved(_) | TokenValue::CustomReserved(_) => ("Reserved", Some(json_string(lexeme))),
+                TokenValue::Number(NumberLiteral { value: NumberValue::Int(value), .. }) => {
+                    ("Number", Some(value.to_string()))
+                }
+                TokenValue::Number(NumberLiteral { value: NumberValue::Float(value), .. }) if value.is_finite() => {
+                    ("Number", Some(value.to_string()))
+                }
+                TokenValue::Number(_) => ("Number", Some("null".to_string())),
+                TokenValue::String(value) => ("String", Some(json_string(value))),
+                TokenValue::ByteString(value) => ("ByteString", Some(json_bytes(value))),
+                TokenValue::Symbol(_) => ("Symbol", Some(json_string(lexeme))),
+                TokenValue::Name(value) => ("Name", Some(json_string(value))),
+                TokenValue::Path(names) => {
+                    let names: Vec<String> = names.iter().map(|name| json_string(name)).collect();
+                    ("Path", Some(format!("[{}]", names.join(","))))
+                }
+                TokenValue::Comment(CommentKind::Line, text) => ("LineComment", Some(json_string(text))),
+                TokenValue::Comment(CommentKind::Block { .. }, text) => ("BlockComment", Some(json_string(text))),
+                TokenValue::Directive(text) => ("Directive", Some(json_string(text))),
+                TokenValue::EOL => ("EOL", None),
+                TokenValue::EOF => continue,
+            };
+            write!(out, "{{\"kind\":\"{kind}\"")?;
+            if let Some(value) = value {
+                write!(out, ",\"value\":{value}")?;
+            }
+            writeln!(out, ",\"line\":{},\"col\":{}}}", token.position.line, token.position.column)?;
+        }
+        Ok(())
+    }
+
+    pub fn debug_table(&self) -> String {
+        let rows: Vec<(String, String, String)> = self
+            .token_list
+            .iter()
+            .map(|token| {
+                let lexeme: String = self
+                    .span_text(token.span)
+                    .chars()
+                    .map(|ch| if ch.is_control() { ch.escape_default().to_string() } else { ch.to_string() })
+                    .collect();
+                (format!("{:?}", token.kind()), lexeme, format!("{}:{}", token.position.line, token.position.column))
+            })
+            .collect();
+        let kind_width = rows.iter().map(|row| row.0.chars().count()).max().unwrap_or(0).max("KIND".len());
+        let lexeme_width = rows.iter().map(|row| row.1.chars().count()).max().unwrap_or(0).max("LEXEME".len());
+        let mut table = format!("{:kind_width$} | {:lexeme_width$} | LINE:COL\n", "KIND", "LEXEME");
+        for (kind, lexeme, position) in rows {
+            table.push_str(&format!("{kind:kind_width$} | {lexeme:lexeme_width$} | {position}\n"));
+        }
+        table
+    }
+
+    pub fn end_position(&self) -> Position {
+        self.last_end_position
+    }
+
+    pub fn progress(&self) -> f64 {
+        if self.source.is_empty() {
+            1.0
+        } else {
+            self.current as f64 / self.source.len() as f64
+        }
+    }
+
+    pub fn bytes_consumed(&self) -> usize {
+        self.offset
+    }
+
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        let text = self.text.split('\n').nth(line.checked_sub(1)?)?;
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    pub fn line_ending(&self, line: usize) -> Option<LineEnding> {
+        self.line_endings.get(line.checked_sub(1)?).copied()
+    }
+
+    pub fn span_text(&self, span: Span) -> &str {
+        &self.text[span.start..span.end]
+    }
+
+    pub fn lexemes(&self) -> Vec<(TokenKind, &str)> {
+        self.token_list
+            .iter()
+            .filter(|token| !matches!(token.value, TokenValue::EOF))
+            .map(|token| (token.kind(), self.span_text(token.span)))
+            .collect()
+    }
+
+    pub fn token_at_offset(&self, offset: usize) -> Option<(usize, &Token)> {
+        let index = self.token_list.partition_point(|token| token.span.end <= offset);
+        let token = self.token_list.get(index)?;
+        token.span.contains(offset).then_some((index, token))
+    }
+
+    pub fn tokens_on_current_line(&self) -> usize {
+        self.line_token_count
+    }
+
+    pub fn tokens_on_line(&self, line: usize) -> &[Token] {
+        let start = self.token_list.partition_point(|token| token.position.line < line);
+        let end = self.token_list.partition_point(|token| token.position.line <= line);
+        &self.token_list[start..end]
+    }
+
+    fn scan_token(&mut self) -> Result<TokenValue, LexError> {
+        self.skip_whitespace();
+        self.align_pointer();
+        if self.is_at_end() {
+            return Ok(TokenValue::EOF);
+        }
+
+        let token = match self.advance() {
+            '\n' => TokenValue::EOL,
+            '+' => TokenValue::Symbol(Symbol::Add),
+            '-' => if self.match_char('-') {
+                self.scan_comment()?
+            } else {
+                TokenValue::Symbol(Symbol::Minus)
+            },
+            '*' => TokenValue::Symbol(Symbol::Multiply),
+            '/' => if self.match_char('/') {
+                self.lua53_symbol(Symbol::FloorDivide)?
+            } else {
+                TokenValue::Symbol(Symbol::Divide)
+            },
+            '%' => TokenValue::Symbol(Symbol::Mod),
+            '^' => TokenValue::Symbol(Symbol::Power),
+            '#' => TokenValue::Symbol(Symbol::Length),
+            '=' => if self.match_char('=') {
+                TokenValue::Symbol(Symbol::Equal)
+            } else {
+                TokenValue::Symbol(Symbol::Assign)
+            },
+            '>' => if self.match_char('=') {
+                TokenValue::Symbol(Symbol::GreaterEqual)
+            } else if self.match_char('>') {
+                self.lua53_symbol(Symbol::ShiftRight)?
+            } else {
+                TokenValue::Symbol(Symbol::Greater)
+            },
+            '<' => if self.match_char('=') {
+                TokenValue::Symbol(Symbol::LessEqual)
+            } else if self.match_char('<') {
+                self.lua53_symbol(Symbol::ShiftLeft)?
+            } else {
+                TokenValue::Symbol(Symbol::Less)
+            },
+            '~' => if self.match_char('=') {
+                TokenValue::Symbol(Symbol::NotEqual)
+            } else {
+                self.lua53_symbol(Symbol::BitXor)?
+            },
+            '&' => self.lua53_symbol(Symbol::BitAnd)?,
+            '|' => self.lua53_symbol(Symbol::BitOr)?,
+            '(' => TokenValue::Symbol(Symbol::LeftParen),
+            ')' => TokenValue::Symbol(Symbol::RightParen),
+            '{' => TokenValue::Symbol(Symbol::LeftBrace),
+            '}' => TokenValue::Symbol(Symbol::RightBrace),
+            '[' => match self.long_bracket_level() {
+                Some(level) => TokenValue::String(self.scan_long_bracket(level)?),
+                None => TokenValue::Symbol(Symbol::LeftBracket),
+            },
+            ']' => TokenValue::Symbol(Symbol::RightBracket),
+            ';' => TokenValue::Symbol(Symbol::Semicolon),
+            ':' => if self.options.version >= LuaVersion::Lua52 && self.match_char(':') {
+                TokenValue::Symbol(Symbol::DoubleColon)
+            } else {
+                TokenValue::Symbol(Symbol::Colon)
+            },
+            ',' => TokenValue::Symbol(Symbol::Comma),
+            '.' => if self.peek().is_ascii_digit() {
+                self.scan_number()?
+            } else if self.match_char('.') {
+                if self.match_char('.') {
+                    TokenValue::Symbol(Symbol::Ellipsis)
+                } else {
+                    TokenValue::Symbol(Symbol::Concat)
+                }
+            } else {
+                TokenValue::Symbol(Symbol::Dot)
+            },
+            '"' | '\'' => self.scan_string()?,
+            '?' => return Err(self.error(LexErrorKind::TernaryOperator)),
+            '$' if self.options.directives && self.at_line_start => self.scan_directive(),
+            ch if ch.is_ascii_digit() => self.scan_number()?,
+            ch if self.is_name_char(ch, true) => self.scan_name()?,
+            ch => return Err(self.error(LexErrorKind::UnexpectedChar(ch))),
+        };
+        Ok(token)
+    }
+
+    fn scan_directive(&mut self) -> TokenValue {
+        while !self.is_at_end() && self.peek() != '\n' {
+            self.advance();
+        }
+        TokenValue::Directive(self.source[self.start + 1..self.current].iter().collect())
+    }
+
+    fn scan_comment(&mut self) -> Result<TokenValue, LexError> {
+        let mut text = String::new();
+        if self.match_char('[') {
+            if let Some(level) = self.long_bracket_level() {
+                let text = self
+                    .scan_long_bracket(level)
+                    .map_err(|_| self.error(LexErrorKind::UnterminatedLongComment))?;
+                return Ok(TokenValue::Comment(CommentKind::Block { level }, text));
+            }
+            text.push('[');
+        }
+        while !self.is_at_end() && self.peek() != '\n' {
+            text.push(self.advance());
+        }
+        Ok(TokenValue::Comment(CommentKind::Line, text))
+    }
+
+    fn long_bracket_level(&self) -> Option<usize> {
+        let level = self.source[self.current..].iter().take_while(|&&ch| ch == '=').count();
+        (self.source.get(self.current + level) == Some(&'[')).then_some(level)
+    }
+
+    fn scan_long_bracket(&mut self, level: usize) -> Result<String, LexError> {
+        for _ in 0..=level {
+            self.advance();
+        }
+        self.match_char('\r');
+        self.match_char('\n');
+        let mut string = String::new();
+        loop {
+            if self.is_at_end() {
+                return Err(self.error(LexErrorKind::UnterminatedLongBracket));
+            }
+            if self.closes_long_bracket(level) {
+                for _ in 0..level + 2 {
+                    self.advance();
+                }
+                return Ok(string);
+            }
+            match self.advance() {
+                // Any of \n, \r, \r\n and \n\r is one line break, stored as \n as in reference Lua.
+                '\r' => {
+                    self.match_char('\n');
+                    string.push('\n');
+                },
+                '\n' => {
+                    self.match_char('\r');
+                    string.push('\n');
+                },
+                ch => string.push(ch),
+            }
+        }
+    }
+
+    fn closes_long_bracket(&self, level: usize) -> bool {
+        let rest = &self.source[self.current..];
+        rest.len() >= level + 2
+            && rest[0] == ']'
+            && rest[1..=level].iter().all(|&ch| ch == '=')
+            && rest[level + 1] == ']'
+    }
+
+    fn scan_number(&mut self) -> Result<TokenValue, LexError> {
+        let mut hex = false;
+        let mut float = false;
+        let mut science = false;
+        if self.previous() == '0' && (self.match_char('x') || self.match_char('X')) {
+            hex = true;
+        } else if self.options.binary_literals
+            && self.previous() == '0'
+            && (self.match_char('b') || self.match_char('B'))
+        {
+            return self.scan_binary();
+        }
+        if self.previous() == '.' {
+            float = true;
+        }
+        let mut signed_power = false;
+        let mut number_power = false;
+        loop {
+            let ch = self.peek();
+            if ch.is_ascii_digit() {
+                if science {
+                    number_power = true;
+                }
+                self.advance();
+            } else if !science && Self::is_exponent_char(ch, hex) {
+                float = true;
+                science = true;
+                self.advance();
+            } else if let 'a'..='f' = ch.to_lowercase().next().unwrap() {
+                if hex && !science {
+                    self.advance();
+                } else {
+                    return Err(self.error(LexErrorKind::MalformedNumber));
+                }
+            } else if ch == '.' {
+                if float {
+                    return Err(self.error(LexErrorKind::MalformedNumber));
+                } else {
+                    self.advance();
+                    float = true;
+                }
+            } else if (ch == '-' || ch == '+') && science && !signed_power && !number_power {
+                signed_power = true;
+                self.advance();
+            } else if ch == '_' && self.options.numeric_separators {
+                let is_digit = |ch: char| if hex && !science { ch.is_ascii_hexdigit() } else { ch.is_ascii_digit() };
+                if !is_digit(self.previous()) || !is_digit(self.peek_next()) {
+                    return Err(self.error(LexErrorKind::MalformedNumber));
+                }
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        if float && self.options.integers_only {
+            return Err(self.error(LexErrorKind::FloatNotAllowed));
+        }
+        self.check_length(self.options.max_number_length)?;
+        let lexeme = self.extract_word();
+        let word = lexeme.replace('_', "");
+        if self.options.exact_numbers {
+            let (mantissa, exponent, radix) = if hex { (&word[2..], ['p', 'P'], 16) } else { (&word[..], ['e', 'E'], 10) };
+            let mantissa = mantissa.split(exponent).next().unwrap();
+            if !mantissa.chars().any(|ch| ch.is_digit(radix)) || science && !number_power {
+                return Err(self.error(LexErrorKind::MalformedNumber));
+            }
+            return Ok(Self::number(NumberValue::Unparsed, lexeme));
+        }
+        let value = if float && hex {
+            Self::hex_float(&word[2..]).map(|value| Self::number(NumberValue::Float(value), lexeme))
+        } else if float {
+            word.parse::<f64>().ok().map(|value| Self::number(NumberValue::Float(value), lexeme))
+        } else if hex {
+            self.hex_integer(&word[2..], lexeme)
+        } else {
+            // Lua has no octal literals; leading zeros are ignored, so `010` is ten.
+            Some(self.decimal_integer(&word, lexeme))
+        };
+        value.ok_or_else(|| self.error(LexErrorKind::MalformedNumber))
+    }
+
+    fn scan_binary(&mut self) -> Result<TokenValue, LexError> {
+        while matches!(self.peek(), '0' | '1') {
+            self.advance();
+        }
+        self.check_length(self.options.max_number_length)?;
+        let lexeme = self.extract_word();
+        if self.is_name_char(self.peek(), false) {
+            return Err(self.error(LexErrorKind::MalformedNumber));
+        }
+        match u64::from_str_radix(&lexeme[2..], 2) {
+            Ok(value) => Ok(self.integer_value(value, lexeme)),
+            Err(_) => Err(self.error(LexErrorKind::MalformedNumber)),
+        }
+    }
+
+    fn number(value: NumberValue, lexeme: String) -> TokenValue {
+        TokenValue::Number(NumberLiteral { value, lexeme })
+    }
+
+    fn integer_value(&self, value: u64, lexeme: String) -> TokenValue {
+        if self.has_integers() {
+            Self::number(NumberValue::Int(value as i64), lexeme)
+        } else {
+            Self::number(NumberValue::Float(value as f64), lexeme)
+        }
+    }
+
+    fn is_exponent_char(ch: char, hex: bool) -> bool {
+        if hex {
+            ch == 'p' || ch == 'P'
+        } else {
+            ch == 'e' || ch == 'E'
+        }
+    }
+
+    fn hex_float(digits: &str) -> Option<f64> {
+        let (mantissa, mut exponent) = match digits.find(['p', 'P']) {
+            Some(index) => (&digits[..index], digits[index + 1..].parse::<i32>().ok()?),
+            None => (digits, 0),
+        };
+        if !mantissa.chars().any(|ch| ch.is_ascii_hexdigit()) {
+            return None;
+        }
+        let mut value = 0.0;
+        let mut fraction = false;
+        for ch in mantissa.chars() {
+            if ch == '.' {
+                fraction = true;
+            } else {
+                value = value * 16.0 + ch.to_digit(16)? as f64;
+                if fraction {
+                    exponent -= 4;
+                }
+            }
+        }
+        Some(value * 2f64.powi(exponent))
+    }
+
+    fn hex_integer(&self, digits: &str, lexeme: String) -> Option<TokenValue> {
+        if digits.is_empty() {
+            return None;
+        }
+        if self.has_integers() {
+            let value = digits.chars().try_fold(0u64, |value, ch| {
+                Some(value.wrapping_mul(16).wrapping_add(ch.to_digit(16)? as u64))
+            })?;
+            return Some(Self::number(NumberValue::Int(value as i64), lexeme));
+        }
+        let value = digits.chars().try_fold(0.0, |value, ch| Some(value * 16.0 + ch.to_digit(16)? as f64))?;
+        Some(Self::number(NumberValue::Float(value), lexeme))
+    }
+
+    fn decimal_integer(&self, word: &str, lexeme: String) -> TokenValue {
+        match word.parse::<i64>() {
+            Ok(value) if self.has_integers() => Self::number(NumberValue::Int(value), lexeme),
+            _ => Self::number(NumberValue::Float(word.parse::<f64>().unwrap()), lexeme),
+        }
+    }
+
+    fn lua53_symbol(&self, symbol: Symbol) -> Result<TokenValue, LexError> {
+        if self.options.version >= LuaVersion::Lua53 {
+            Ok(TokenValue::Symbol(symbol))
+        } else {
+            Err(self.error(LexErrorKind::UnsupportedOperator(self.extract_word())))
+        }
+    }
+
+    fn has_integers(&self) -> bool {
+        self.options.version >= LuaVersion::Lua53
+    }
+
+    fn scan_string(&mut self) -> Result<TokenValue, LexError> {
+        let str_tag = self.previous();
+        let mut bytes = vec![];
+        while self.peek() != str_tag {
+            if self.is_at_end() || self.peek() == '\n' {
+                let error = self.error(LexErrorKind::UnterminatedString);
+                if !self.options.recover {
+                    return Err(error);
+                }
+                self.push_diagnostic(error);
+                return Ok(Self::string_value(bytes));
+            }
+            if self.match_char('\\') {
+                self.parse_escape(&mut bytes)?;
+            } else {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(self.advance().encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+        self.advance();
+        Ok(Self::string_value(bytes))
+    }
+
+    fn string_value(bytes: Vec<u8>) -> TokenValue {
+        match String::from_utf8(bytes) {
+            Ok(string) => TokenValue::String(string),
+            Err(error) => TokenValue::ByteString(error.into_bytes()),
+        }
+    }
+
+    fn scan_name(&mut self) -> Result<TokenValue, LexError> {
+        while self.is_name_char(self.peek(), false) {
+            self.advance();
+        }
+        self.check_length(self.options.max_name_length)?;
+        let word = self.extract_word();
+        Ok(if let Some(value) = self.options.name_classifier.and_then(|classify| classify(&word)) {
+            value
+        } else if let Some(&reserved) = self.reserved_words.get(&word) {
+            self.keyword_positions.push((reserved, self.start_position));
+            TokenValue::Reserved(reserved)
+        } else if self.options.extra_keywords.contains(&word) {
+            TokenValue::CustomReserved(word)
+        } else {
+            TokenValue::Name(word)
+        })
+    }
+
+    fn parse_escape(&mut self, bytes: &mut Vec<u8>) -> Result<(), LexError> {
+        if self.is_at_end() {
+            return Err(self.error(LexErrorKind::UnterminatedString));
+        }
+        let byte = match self.advance() {
+            '\\' => b'\\',
+            'a' => b'\x07',
+            'b' => b'\x08',
+            'f' => b'\x0C',
+            'n' | '\n' => b'\n',
+            'r' => b'\r',
+            't' => b'\t',
+            'v' => b'\x0B',
+            '\'' => b'\'',
+            '"' => b'"',
+            'x' => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match self.peek().to_digit(16) {
+                        Some(digit) => {
+                            self.advance();
+                            value = value * 16 + digit;
+                        },
+                        None => return Err(self.error(LexErrorKind::InvalidEscape)),
+                    }
+                }
+                value as u8
+            },
+            ch if ch.is_ascii_digit() => {
+                let mut value = ch.to_digit(10).unwrap();
+                for _ in 0..2 {
+                    match self.peek().to_digit(10) {
+                        Some(digit) => {
+                            self.advance();
+                            value = value * 10 + digit;
+                        },
+                        None => break,
+                    }
+                }
+                match u8::try_from(value) {
+                    Ok(byte) => byte,
+                    Err(_) => return Err(self.error(LexErrorKind::InvalidEscape)),
+                }
+            },
+            ch if self.options.lenient_escapes => {
+                let mut buffer = [0; 4];
+                bytes.push(b'\\');
+                bytes.extend_from_slice(ch.encode_utf8(&mut buffer).as_bytes());
+                return Ok(());
+            },
+            _ => return Err(self.error(LexErrorKind::InvalidEscape)),
+        };
+        bytes.push(byte);
+        Ok(())
+    }
+
+    fn push_diagnostic(&mut self, error: LexError) {
+        self.diagnostics.push(Diagnostic {
+            range: (error.position, self.position),
+            severity: Severity::Error,
+            message: error.kind.to_string(),
+            code: error.kind,
+        });
+    }
+
+    fn error(&self, kind: LexErrorKind) -> LexError {
+        LexError {
+            kind,
+            position: self.start_position,
+        }
+    }
+
+    // Checked before `extract_word` so an oversized token is rejected without allocating it.
+    fn check_length(&self, limit: Option<usize>) -> Result<(), LexError> {
+        if limit.is_some_and(|limit| self.current - self.start > limit) {
+            return Err(self.error(LexErrorKind::TokenTooLong));
+        }
+        Ok(())
+    }
+
+    fn extract_word(&self) -> String {
+        String::from_iter(&self.source[self.start..self.current])
+    }
+
+    fn skip_whitespace(&mut self) {
+        let line_start = self.position.column == 1;
+        self.at_line_start = line_start;
+        let mut indentation = Indentation { line: self.position.line, spaces: 0, tabs: 0 };
+        let (start_offset, start_position) = (self.offset, self.position);
+        while self.peek() != '\n' && Self::is_lua_whitespace(self.peek()) {
+            match self.advance() {
+                ' ' => indentation.spaces += 1,
+                '\t' => indentation.tabs += 1,
+                _ => {},
+            }
+        }
+        if self.options.collect_trailing_whitespace && self.peek() == '\n' {
+            let end = if self.previous() == '\r' { self.offset - 1 } else { self.offset };
+            if end > start_offset {
+                self.trailing_whitespace.push((start_position, Span { start: start_offset, end }));
+            }
+        }
+        if self.options.collect_indentation && line_start && !self.is_at_end() && self.peek() != '\n' {
+            self.indentation.push(indentation);
+        }
+    }
+
+    fn is_lua_whitespace(ch: char) -> bool {
+        matches!(ch, ' ' | '\t' | '\n' | '\r' | '\x0B' | '\x0C')
+    }
+
+    fn align_pointer(&mut self) {
+        self.start = self.current;
+        self.start_offset = self.offset;
+        self.start_position = self.position;
+    }
+
+    fn is_name_char(&self, ch: char, start: bool) -> bool {
+        Self::is_lua_name_char(ch, start)
+            || self.options.extra_name_start_chars.contains(&ch)
+            || (!start && self.options.extra_name_chars.contains(&ch))
+    }
+
+    fn is_lua_name_char(ch: char, start: bool) -> bool {
+        if ch.is_ascii() {
+            ch == '_' || ch.is_ascii_alphabetic() || (!start && ch.is_ascii_digit())
+        } else if start {
+            ch.is_alphabetic()
+        } else {
+            ch.is_alphanumeric()
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.source.len()
+    }
+
+    fn advance(&mut self) -> char {
+        if self.is_at_end() {
+            '\0'
+        } else {
+            let ch = self.source[self.current];
+            self.current += 1;
+            self.offset += ch.len_utf8();
+            if ch == '\n' {
+                if self.options.collect_line_endings {
+                    let crlf = self.current >= 2 && self.source[self.current - 2] == '\r';
+                    self.line_endings.push(if crlf { LineEnding::CrLf } else { LineEnding::Lf });
+                }
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+            ch
+        }
+    }
+
+    fn previous(&self) -> char {
+        if self.current == 0 {
+            '\0'
+        } else {
+            self.source[self.current - 1]
+        }
+    }
+
+    fn peek(&self) -> char {
+        if self.is_at_end() {
+            '\0'
+        } else {
+            self.source[self.current]
+        }
+    }
+
+    fn peek_next(&self) -> char {
+        self.source.get(self.current + 1).copied().unwrap_or('\0')
+    }
+
+    fn match_char(&mut self, target: char) -> bool {
+        if self.is_at_end() {
+            false
+        } else {
+            if self.peek() == target {
+                self.advance();
+                true
+            } else {
+                false
+            }
+        }
+    }
+}
+
+impl Tokenizer for Lexer {
+    fn next_token(&mut self) -> Result<Token, LexError> {
+        Lexer::next_token(self)
+    }
+
+    fn peek_token(&mut self) -> Result<&Token, LexError> {
+        Lexer::peek_token(self)
+    }
+
+    fn position(&self) -> Position {
+        match &self.peeked {
+            Some(token) => token.position,
+            None => self.position,
+        }
+    }
+}
+
+pub fn lex_strict(source: &str) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.scan_tokens()?;
+    Ok(lexer.token_list)
+}
+
+pub fn lex_recover(source: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let options = LexerOptions { recover: true, ..LexerOptions::default() };
+    let mut lexer = Lexer::with_options(source.to_string(), options);
+    if let Err(error) = lexer.scan_tokens() {
+        lexer.push_diagnostic(error);
+    }
+    (lexer.token_list, lexer.diagnostics)
+}
+
+/// Decodes raw input as UTF-8, reporting the first invalid byte at its line and column.
+/// Non-UTF-8 bytes are rejected even inside string literals; write them as `\xHH` escapes instead.
+pub fn decode_source(bytes: Vec<u8>) -> Result<String, (LexError, String)> {
+    String::from_utf8(bytes).map_err(|error| {
+        let valid = &error.as_bytes()[..error.utf8_error().valid_up_to()];
+        let valid = std::str::from_utf8(valid).unwrap();
+        let line_start = valid.rfind('\n').map_or(0, |index| index + 1);
+        let position = Position {
+            line: valid.matches('\n').count() + 1,
+            column: valid[line_start..].chars().count() + 1,
+        };
+        let kind = LexErrorKind::InvalidUtf8(error.as_bytes()[valid.len()]);
+        (LexError { kind, position }, String::from_utf8_lossy(error.as_bytes()).into_owned())
+    })
+}
+
+pub fn is_valid_identifier(name: &str, version: LuaVersion) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(ch) if Lexer::is_lua_name_char(ch, true) => {},
+        _ => return false,
+    }
+    chars.all(|ch| Lexer::is_lua_name_char(ch, false))
+        && !RESERVED_WORDS.iter().any(|&(word, _)| word == name)
+        && !(version >= LuaVersion::Lua52 && name == "goto")
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::from("\"");
+    for ch in string.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| byte.to_string()).collect();
+    format!("[{}]", bytes.join(","))
+}
+
+// Builds the expected `(kind, lexeme)` pairs of `Lexer::lexemes`, e.g.
+// `tok![local, name "x", =, num 1, sym "~=", str "s", eol]`.
+#[cfg(test)]
+macro_rules! tok {
+    (@acc [$($out:expr),*]) => { vec![$($out),*] };
+    (@acc [$($out:expr),*] name $name:literal $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::Name, $name)] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] num $number:literal $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::Number, stringify!($number))] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] str $string:literal $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::String, stringify!($string))] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] sym $symbol:literal $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::Symbol, $symbol)] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] eol $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::EOL, "\n")] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] $word:ident $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::Reserved, stringify!($word))] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] $symbol:tt $(, $($rest:tt)*)?) => {
+        tok!(@acc [$($out,)* (TokenKind::Symbol, stringify!($symbol))] $($($rest)*)?)
+    };
+    ($($tokens:tt)*) => { tok!(@acc [] $($tokens)*) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> Lexer {
+        lex_with(source, LexerOptions::default())
+    }
+
+    fn lex_with(source: &str, options: LexerOptions) -> Lexer {
+        let mut lexer = Lexer::with_options(source.to_string(), options);
+        lexer.scan_tokens().unwrap();
+        lexer
+    }
+
+    fn lex_error(source: &str) -> LexErrorKind {
+        Lexer::new(source.to_string()).scan_tokens().unwrap_err().kind
+    }
+
+    fn values(source: &str) -> Vec<TokenValue> {
+        let tokens = lex(source).token_list.into_iter();
+        tokens.map(|token| token.value).filter(|value| !matches!(value, TokenValue::EOF)).collect()
+    }
+
+    fn number(value: NumberValue, lexeme: &str) -> TokenValue {
+        TokenValue::Number(NumberLiteral { value, lexeme: lexeme.to_string() })
+    }
+
+    // An integer literal under the default version: an integer from 5.3 on, a float before.
+    fn integer(value: i64, lexeme: &str) -> TokenValue {
+        if SUPPORTED_LUA_VERSION >= LuaVersion::Lua53 {
+            number(NumberValue::Int(value), lexeme)
+        } else {
+            number(NumberValue::Float(value as f64), lexeme)
+        }
+    }
+
+    #[test]
+    fn keyword_positions_record_reserved_words() {
+        let lexer = lex("local end = 1\n  return");
+        assert_eq!(
+            lexer.keyword_positions,
+            [
+                (ReservedWord::Local, Position { line: 1, column: 1 }),
+                (ReservedWord::End, Position { line: 1, column: 7 }),
+                (ReservedWord::Return, Position { line: 2, column: 3 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_text_strips_line_breaks() {
+        let lexer = lex("a = 1\r\nb = 2\n");
+        assert_eq!(lexer.line_text(1), Some("a = 1"));
+        assert_eq!(lexer.line_text(2), Some("b = 2"));
+        assert_eq!(lexer.line_text(3), Some(""));
+        assert_eq!(lexer.line_text(0), None);
+        assert_eq!(lexer.line_text(4), None);
+    }
+
+    #[test]
+    fn unescaped_newline_ends_a_short_string() {
+        let error = Lexer::new("s = 'abc\ndef'".to_string()).scan_tokens().unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::UnterminatedString);
+        assert_eq!(error.position, Position { line: 1, column: 5 });
+    }
+
+    #[test]
+    fn non_ascii_letters_are_name_chars() {
+        assert_eq!(values("名前 x1é _a1"), [
+            TokenValue::Name("名前".to_string()),
+            TokenValue::Name("x1é".to_string()),
+            TokenValue::Name("_a1".to_string()),
+        ]);
+    }
+
+    #[cfg(feature = "lua53")]
+    #[test]
+    fn lua53_operators_are_symbols() {
+        assert_eq!(
+            values("a // b & c | d ~ e << f >> g"),
+            [
+                TokenValue::Name("a".to_string()),
+                TokenValue::Symbol(Symbol::FloorDivide),
+                TokenValue::Name("b".to_string()),
+                TokenValue::Symbol(Symbol::BitAnd),
+                TokenValue::Name("c".to_string()),
+                TokenValue::Symbol(Symbol::BitOr),
+                TokenValue::Name("d".to_string()),
+                TokenValue::Symbol(Symbol::BitXor),
+                TokenValue::Name("e".to_string()),
+                TokenValue::Symbol(Symbol::ShiftLeft),
+                TokenValue::Name("f".to_string()),
+                TokenValue::Symbol(Symbol::ShiftRight),
+                TokenValue::Name("g".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(not(feature = "lua53"))]
+    #[test]
+    fn lua53_operators_are_rejected() {
+        for operator in ["//", "&", "|", "~", "<<", ">>"] {
+            let error = Lexer::new(format!("a {operator} b")).scan_tokens().unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::UnsupportedOperator(operator.to_string()));
+        }
+    }
+
+    #[cfg(feature = "lua53")]
+    #[test]
+    fn integer_literals_are_integers() {
+        assert_eq!(
+            values("10 0x10 1.0"),
+            [
+                number(NumberValue::Int(10), "10"),
+                number(NumberValue::Int(16), "0x10"),
+                number(NumberValue::Float(1.0), "1.0"),
+            ]
+        );
+    }
+
+    #[cfg(not(feature = "lua53"))]
+    #[test]
+    fn integer_literals_are_floats() {
+        assert_eq!(
+            values("10 0x10 1.0"),
+            [
+                integer(10, "10"),
+                integer(16, "0x10"),
+                number(NumberValue::Float(1.0), "1.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn peek_token_does_not_consume() {
+        let mut lexer = Lexer::new("a b".to_string());
+        assert_eq!(lexer.peek_token().unwrap().value, TokenValue::Name("a".to_string()));
+        assert_eq!(lexer.peek_token().unwrap().value, TokenValue::Name("a".to_string()));
+        assert_eq!(lexer.next_token().unwrap().value, TokenValue::Name("a".to_string()));
+        assert_eq!(lexer.next_token().unwrap().value, TokenValue::Name("b".to_string()));
+        assert_eq!(lexer.next_token().unwrap().value, TokenValue::EOF);
+        assert_eq!(lexer.next_token().unwrap().value, TokenValue::EOF);
+    }
+
+    #[test]
+    fn hex_and_decimal_numbers_do_not_share_state() {
+        assert_eq!(
+            values("0x1p4 1e1 0xA.8 .5 0x.1P-4"),
+            [
+                number(NumberValue::Float(16.0), "0x1p4"),
+                number(NumberValue::Float(10.0), "1e1"),
+                number(NumberValue::Float(10.5), "0xA.8"),
+                number(NumberValue::Float(0.5), ".5"),
+                number(NumberValue::Float(1.0 / 256.0), "0x.1P-4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_carry_spans_and_positions() {
+        let lexer = lex("x = 'é'\n  y");
+        let spans: Vec<_> = lexer.token_list.iter().map(|token| (token.span.start, token.span.end)).collect();
+        assert_eq!(spans, [(0, 1), (2, 3), (4, 8), (8, 9), (11, 12), (12, 12)]);
+        assert_eq!(lexer.token_list[4].position, Position { line: 2, column: 3 });
+    }
+
+    #[test]
+    fn dump_json_writes_one_object_per_token() {
+        let mut out = vec![];
+        lex("s = \"\\xFF\" -- c\n").dump_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"kind\":\"Name\",\"value\":\"s\",\"line\":1,\"col\":1}\n\
+             {\"kind\":\"Symbol\",\"value\":\"=\",\"line\":1,\"col\":3}\n\
+             {\"kind\":\"ByteString\",\"value\":[255],\"line\":1,\"col\":5}\n\
+             {\"kind\":\"EOL\",\"line\":1,\"col\":16}\n"
+        );
+    }
+
+    #[test]
+    fn progress_and_bytes_consumed_follow_the_scan() {
+        let mut lexer = Lexer::new("é = 1".to_string());
+        assert_eq!(lexer.progress(), 0.0);
+        lexer.next_token().unwrap();
+        assert_eq!(lexer.bytes_consumed(), 2);
+        assert_eq!(lexer.progress(), 0.2);
+        lexer.scan_tokens().unwrap();
+        assert_eq!(lexer.bytes_consumed(), 6);
+        assert_eq!(lexer.progress(), 1.0);
+        assert_eq!(Lexer::new(String::new()).progress(), 1.0);
+    }
+
+    #[test]
+    fn byte_escapes_keep_invalid_utf8() {
+        assert_eq!(values(r#""\xFF\65\x41""#), [TokenValue::ByteString(vec![0xFF, b'A', b'A'])]);
+        assert_eq!(values(r#""\xC3\xA9""#), [TokenValue::String("é".to_string())]);
+    }
+
+    #[test]
+    fn indentation_is_collected_per_line() {
+        let options = LexerOptions { collect_indentation: true, ..LexerOptions::default() };
+        let lexer = lex_with("a\n  \tb\n\n\t\n    c", options);
+        assert_eq!(
+            lexer.indentation,
+            [
+                Indentation { line: 1, spaces: 0, tabs: 0 },
+                Indentation { line: 2, spaces: 2, tabs: 1 },
+                Indentation { line: 5, spaces: 4, tabs: 0 },
+            ]
+        );
+        assert!(lex("  a").indentation.is_empty());
+    }
+
+    #[test]
+    fn extra_name_chars_extend_identifiers() {
+        let options = LexerOptions {
+            extra_name_start_chars: vec!['@'],
+            extra_name_chars: vec!['$'],
+            ..LexerOptions::default()
+        };
+        let tokens = lex_with("@a$b a$", options.clone()).token_list;
+        assert_eq!(tokens[0].value, TokenValue::Name("@a$b".to_string()));
+        assert_eq!(tokens[1].value, TokenValue::Name("a$".to_string()));
+        let error = Lexer::with_options("$a".to_string(), options).scan_tokens().unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::UnexpectedChar('$'));
+        assert_eq!(lex_error("@a"), LexErrorKind::UnexpectedChar('@'));
+    }
+
+    #[test]
+    fn lex_error_kinds_are_matchable_and_displayed() {
+        let error = Lexer::new("x = 1\ny = \"a".to_string()).scan_tokens().unwrap_err();
+        assert!(matches!(error.kind, LexErrorKind::UnterminatedString));
+        assert_eq!(error.to_string(), "2:5: Unfinished string.");
+        assert_eq!(LexErrorKind::UnexpectedChar('@').to_string(), "Invalid token '@'.");
+        assert_eq!(LexErrorKind::UnsupportedOperator("//".to_string()).to_string(), "'//' requires Lua 5.3.");
+        assert_eq!(lex_error("x = 1.2.3"), LexErrorKind::MalformedNumber);
+    }
+
+    #[test]
+    fn multi_char_tokens_span_all_their_chars() {
+        let tokens = lex("a == b ... c").token_list;
+        assert_eq!(tokens[1].span, Span { start: 2, end: 4 });
+        assert_eq!(tokens[1].position, Position { line: 1, column: 3 });
+        assert_eq!(tokens[3].span, Span { start: 7, end: 10 });
+        assert_eq!(tokens[4].position, Position { line: 1, column: 12 });
+    }
+
+    #[test]
+    fn dump_tokens_matches_the_fixture_snapshot() {
+        let mut out = vec![];
+        lex(include_str!("../test_code.lua")).dump_tokens(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), include_str!("../test_code.out"));
+    }
+
+    #[test]
+    fn hex_escape_needs_two_digits() {
+        assert_eq!(values(r#""\x41""#), [TokenValue::String("A".to_string())]);
+        assert_eq!(lex_error(r#""\x4""#), LexErrorKind::InvalidEscape);
+        assert_eq!(lex_error(r#""\x"#), LexErrorKind::InvalidEscape);
+    }
+
+    #[test]
+    fn concatenation_may_start_the_next_line() {
+        let tokens = values("s = 1\n..x");
+        assert_eq!(tokens[3], TokenValue::EOL);
+        assert_eq!(tokens[4], TokenValue::Symbol(Symbol::Concat));
+        assert_eq!(tokens[5], TokenValue::Name("x".to_string()));
+    }
+
+    #[test]
+    fn numeric_separators_sit_between_digits() {
+        let options = LexerOptions { numeric_separators: true, ..LexerOptions::default() };
+        let tokens = lex_with("1_000 0xFF_FF 1_0.5_0", options.clone()).token_list;
+        assert_eq!(tokens[0].value, integer(1000, "1_000"));
+        assert_eq!(tokens[1].value, integer(65535, "0xFF_FF"));
+        assert_eq!(tokens[2].value, number(NumberValue::Float(10.5), "1_0.5_0"));
+        for source in ["1__0", "1_", "1_.5"] {
+            let error = Lexer::with_options(source.to_string(), options.clone()).scan_tokens().unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::MalformedNumber, "{source:?}");
+        }
+        assert_eq!(values("1_000")[1], TokenValue::Name("_000".to_string()));
+    }
+
+    #[test]
+    fn reserved_word_spellings_round_trip() {
+        for (word, reserved) in RESERVED_WORDS {
+            assert_eq!(reserved.as_str(), word);
+            assert_eq!(values(word), [TokenValue::Reserved(reserved)]);
+        }
+        assert_eq!(ReservedWord::Goto.as_str(), "goto");
+    }
+
+    #[test]
+    fn numbers_stop_at_operators() {
+        let tokens = values("1+2-3*4/5%6^7 1 ..2 3==3");
+        let symbols: Vec<Symbol> = tokens
+            .iter()
+            .filter_map(|value| match value {
+                TokenValue::Symbol(symbol) => Some(*symbol),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tokens.len(), 19);
+        assert_eq!(
+            symbols,
+            [
+                Symbol::Add,
+                Symbol::Minus,
+                Symbol::Multiply,
+                Symbol::Divide,
+                Symbol::Mod,
+                Symbol::Power,
+                Symbol::Concat,
+                Symbol::Equal,
+            ]
+        );
+    }
+
+    #[test]
+    fn number_lexemes_keep_their_spelling() {
+        let TokenValue::Number(hex) = &values("0Xa.8P1")[0] else { panic!() };
+        assert_eq!(hex.lexeme, "0Xa.8P1");
+        assert_eq!(hex.hex_prefix(), Some("0X"));
+        assert_eq!(hex.exponent_marker(), Some('P'));
+        let TokenValue::Number(decimal) = &values("1E5")[0] else { panic!() };
+        assert_eq!(decimal.hex_prefix(), None);
+        assert_eq!(decimal.exponent_marker(), Some('E'));
+    }
+
+    #[test]
+    fn identifiers_exclude_keywords_of_the_version() {
+        assert!(is_valid_identifier("_x1", LuaVersion::Lua51));
+        assert!(!is_valid_identifier("1x", LuaVersion::Lua51));
+        assert!(!is_valid_identifier("end", LuaVersion::Lua51));
+        assert!(is_valid_identifier("goto", LuaVersion::Lua51));
+        assert!(!is_valid_identifier("goto", LuaVersion::Lua52));
+        assert!(!is_valid_identifier("goto", LuaVersion::Lua53));
+    }
+
+    #[test]
+    fn goto_is_reserved_from_lua52() {
+        let lua51 = LexerOptions { version: LuaVersion::Lua51, ..LexerOptions::default() };
+        let tokens = lex_with("local goto = 1", lua51).token_list;
+        assert_eq!(tokens[1].value, TokenValue::Name("goto".to_string()));
+        let lua52 = LexerOptions { version: LuaVersion::Lua52, ..LexerOptions::default() };
+        let lexer = lex_with("local goto = 1", lua52);
+        assert_eq!(lexer.token_list[1].value, TokenValue::Reserved(ReservedWord::Goto));
+        assert_eq!(lexer.keyword_positions[1], (ReservedWord::Goto, Position { line: 1, column: 7 }));
+    }
+
+    #[test]
+    fn end_position_ignores_trailing_comments() {
+        let mut lexer = Lexer::new("x = 10 -- done\n".to_string());
+        lexer.next_token().unwrap();
+        assert_eq!(lexer.end_position(), Position { line: 1, column: 2 });
+        lexer.scan_tokens().unwrap();
+        assert_eq!(lexer.end_position(), Position { line: 2, column: 1 });
+        let mut lexer = Lexer::new("x = 10 -- done".to_string());
+        lexer.scan_tokens().unwrap();
+        assert_eq!(lexer.end_position(), Position { line: 1, column: 7 });
+    }
+
+    #[test]
+    fn binary_literals_need_the_option() {
+        let options = LexerOptions { binary_literals: true, ..LexerOptions::default() };
+        let tokens = lex_with("0b1010 0B1", options.clone()).token_list;
+        assert_eq!(tokens[0].value, integer(10, "0b1010"));
+        assert_eq!(tokens[1].value, integer(1, "0B1"));
+        for source in ["0b12", "0b1x"] {
+            let error = Lexer::with_options(source.to_string(), options.clone()).scan_tokens().unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::MalformedNumber);
+        }
+        assert_eq!(lex_error("0b1"), LexErrorKind::MalformedNumber);
+    }
+
+    #[test]
+    fn recover_mode_closes_an_unterminated_string() {
+        let options = LexerOptions { recover: true, ..LexerOptions::default() };
+        let lexer = lex_with("s = 'abc\nt = 1", options);
+        let values: Vec<&TokenValue> = lexer.token_list.iter().map(|token| &token.value).collect();
+        assert_eq!(values[2], &TokenValue::String("abc".to_string()));
+        assert_eq!(values[3], &TokenValue::EOL);
+        assert_eq!(values[4], &TokenValue::Name("t".to_string()));
+        assert_eq!(lexer.diagnostics.len(), 1);
+        assert_eq!(lexer.diagnostics[0].code, LexErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn token_kinds_order_and_hash() {
+        let mut kinds: Vec<TokenKind> = lex("x = 'a' -- c\n").token_list.iter().map(Token::kind).collect();
+        kinds.sort();
+        assert_eq!(kinds, [TokenKind::String, TokenKind::Symbol, TokenKind::Name, TokenKind::EOL, TokenKind::EOF]);
+        let mut counts: HashMap<TokenKind, usize> = HashMap::new();
+        for token in lex("a b.c 1").token_list {
+            *counts.entry(token.kind()).or_default() += 1;
+        }
+        assert_eq!(counts[&TokenKind::Name], 3);
+        assert_eq!(counts[&TokenKind::Number], 1);
+        assert_eq!(TokenValue::Path(vec![]).kind(), TokenKind::Name);
+    }
+
+    #[test]
+    fn keyword_set_can_be_customised() {
+        let options = LexerOptions {
+            extra_keywords: vec!["continue".to_string()],
+            removed_keywords: vec!["end".to_string()],
+            ..LexerOptions::default()
+        };
+        let lexer = lex_with("continue end", options);
+        assert_eq!(lexer.token_list[0].value, TokenValue::CustomReserved("continue".to_string()));
+        assert_eq!(lexer.token_list[1].value, TokenValue::Name("end".to_string()));
+        assert!(lexer.keyword_positions.is_empty());
+    }
+
+    #[test]
+    fn whitespace_is_the_lua_set() {
+        let names = [TokenValue::Name("a".to_string()), TokenValue::Name("b".to_string())];
+        assert_eq!(values("a\x0B\x0C\t\rb"), names);
+        assert_eq!(lex_error("a\u{A0}b"), LexErrorKind::UnexpectedChar('\u{A0}'));
+    }
+
+    #[test]
+    fn comments_are_kept_with_their_kind() {
+        let options = LexerOptions { keep_comments: true, ..LexerOptions::default() };
+        let lexer = lex_with("-- line\n--[==[ block\n]==] x", options);
+        assert_eq!(lexer.token_list[0].value, TokenValue::Comment(CommentKind::Line, " line".to_string()));
+        let block = TokenValue::Comment(CommentKind::Block { level: 2 }, " block\n".to_string());
+        assert_eq!(lexer.token_list[2].value, block);
+        assert_eq!(lexer.token_list[3].value, TokenValue::Name("x".to_string()));
+        assert_eq!(values("-- line\nx"), [TokenValue::EOL, TokenValue::Name("x".to_string())]);
+    }
+
+    #[test]
+    fn can_begin_expression_matches_expression_starts() {
+        let options = LexerOptions { version: LuaVersion::Lua53, ..LexerOptions::default() };
+        let starts: Vec<bool> = lex_with("x 1 's' ( { - # ~ ... not nil function ) = and end", options)
+            .token_list
+            .iter()
+            .map(Token::can_begin_expression)
+            .collect();
+        assert_eq!(
+            starts,
+            [true, true, true, true, true, true, true, true, true, true, true, true, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn vararg_follows_concatenation() {
+        let concat = [TokenValue::Symbol(Symbol::Concat), TokenValue::Symbol(Symbol::Ellipsis)];
+        assert_eq!(values("c = a.. ...")[3..], concat);
+        assert_eq!(
+            values("f(...)"),
+            [
+                TokenValue::Name("f".to_string()),
+                TokenValue::Symbol(Symbol::LeftParen),
+                TokenValue::Symbol(Symbol::Ellipsis),
+                TokenValue::Symbol(Symbol::RightParen),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_on_line_selects_one_line() {
+        let lexer = lex("a = 1\n\nb c\n");
+        assert_eq!(lexer.tokens_on_line(1).len(), 4);
+        assert_eq!(lexer.tokens_on_line(2).len(), 1);
+        let values: Vec<&TokenValue> = lexer.tokens_on_line(3).iter().map(|token| &token.value).collect();
+        assert_eq!(values, [&TokenValue::Name("b".to_string()), &TokenValue::Name("c".to_string()), &TokenValue::EOL]);
+        assert!(lexer.tokens_on_line(9).is_empty());
+    }
+
+    #[test]
+    fn render_shows_context_lines() {
+        let source = "a = 1\nb = 'x\nc = 3\nd = 4";
+        let error = Lexer::new(source.to_string()).scan_tokens().unwrap_err();
+        assert_eq!(error.render(source, 1), "2:5: Unfinished string.\n1 | a = 1\n2 | b = 'x\n  |     ^\n3 | c = 3\n");
+        assert_eq!(error.render(source, 0), "2:5: Unfinished string.\n2 | b = 'x\n  |     ^\n");
+        let error = Lexer::new("\tx = ?".to_string()).scan_tokens().unwrap_err();
+        assert!(error.render("\tx = ?", 5).ends_with("1 | \tx = ?\n  | \t    ^\n"));
+    }
+
+    #[test]
+    fn backslash_at_end_of_input_is_unfinished() {
+        assert_eq!(lex_error("'abc\\"), LexErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn unterminated_long_comment_has_its_own_error() {
+        let error = Lexer::new("x = 1 --[[ open".to_string()).scan_tokens().unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::UnterminatedLongComment);
+        assert_eq!(error.position, Position { line: 1, column: 7 });
+        assert_eq!(lex_error("[[ open"), LexErrorKind::UnterminatedLongBracket);
+    }
+
+    #[test]
+    fn span_text_is_the_raw_spelling() {
+        let lexer = lex("s = 'a\\tb'");
+        assert_eq!(lexer.span_text(lexer.token_list[2].span), "'a\\tb'");
+        assert_eq!(lexer.token_list[2].string_value(), Some(&b"a\tb"[..]));
+    }
+
+    #[test]
+    fn final_eol_leaves_exactly_one_eol() {
+        let options = LexerOptions { final_eol: true, ..LexerOptions::default() };
+        let kinds = |source: &str| -> Vec<TokenKind> {
+            lex_with(source, options.clone()).token_list.iter().map(Token::kind).collect()
+        };
+        for source in ["x", "x\n", "x\n\n\n", "x \r\n\t\n  "] {
+            assert_eq!(kinds(source), [TokenKind::Name, TokenKind::EOL, TokenKind::EOF], "{source:?}");
+        }
+        assert_eq!(
+            kinds("x\n\ny\n\n"),
+            [TokenKind::Name, TokenKind::EOL, TokenKind::EOL, TokenKind::Name, TokenKind::EOL, TokenKind::EOF]
+        );
+    }
+
+    #[test]
+    fn version_option_gates_lua53_tokens() {
+        let lua51 = LexerOptions { version: LuaVersion::Lua51, ..LexerOptions::default() };
+        let mut lexer = Lexer::with_options("a // b".to_string(), lua51.clone());
+        assert_eq!(lexer.version(), LuaVersion::Lua51);
+        assert_eq!(lexer.scan_tokens().unwrap_err().kind, LexErrorKind::UnsupportedOperator("//".to_string()));
+        assert_eq!(lex_with("7", lua51).token_list[0].value, number(NumberValue::Float(7.0), "7"));
+
+        let lua53 = LexerOptions { version: LuaVersion::Lua53, ..LexerOptions::default() };
+        assert_eq!(lex_with("a // b", lua53.clone()).lexemes(), tok![name "a", sym "//", name "b"]);
+        assert_eq!(lex_with("7", lua53).token_list[0].value, number(NumberValue::Int(7), "7"));
+    }
+
+    #[test]
+    fn default_version_is_the_supported_one() {
+        assert_eq!(LuaVersion::default(), SUPPORTED_LUA_VERSION);
+        assert_eq!(Lexer::new(String::new()).version(), SUPPORTED_LUA_VERSION);
+        let expected = if cfg!(feature = "lua53") { LuaVersion::Lua53 } else { LuaVersion::Lua51 };
+        assert_eq!(SUPPORTED_LUA_VERSION, expected);
+    }
+
+    #[test]
+    fn decode_source_reports_invalid_utf8() {
+        assert_eq!(decode_source(b"x = 1".to_vec()), Ok("x = 1".to_string()));
+        let (error, lossy) = decode_source(b"x = 1\ny = '\xFF'".to_vec()).unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::InvalidUtf8(0xFF));
+        assert_eq!(error.position, Position { line: 2, column: 6 });
+        assert_eq!(lossy, "x = 1\ny = '\u{FFFD}'");
+        assert_eq!(error.to_string(), "2:6: Invalid UTF-8 byte 0xFF.");
+        let (error, _) = decode_source(b"x = 1 \xFF y".to_vec()).unwrap_err();
+        assert_eq!(error.position, Position { line: 1, column: 7 });
+    }
+
+    #[test]
+    fn directives_are_whole_lines_at_line_start() {
+        let options = LexerOptions { directives: true, ..LexerOptions::default() };
+        let lexer = lex_with("$debug on\nx = 1", options.clone());
+        assert_eq!(lexer.token_list[0].value, TokenValue::Directive("debug on".to_string()));
+        assert_eq!(lexer.token_list[1].value, TokenValue::EOL);
+        let error = Lexer::with_options("x $y".to_string(), options).scan_tokens().unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::UnexpectedChar('$'));
+        assert_eq!(lex_error("$debug"), LexErrorKind::UnexpectedChar('$'));
+    }
+
+    #[test]
+    fn operators_at_end_of_input() {
+        let options = LexerOptions { version: LuaVersion::Lua53, ..LexerOptions::default() };
+        let cases = [
+            ("=", Symbol::Assign),
+            ("==", Symbol::Equal),
+            ("<", Symbol::Less),
+            ("<=", Symbol::LessEqual),
+            (">", Symbol::Greater),
+            ("~", Symbol::BitXor),
+            ("~=", Symbol::NotEqual),
+            (".", Symbol::Dot),
+            ("..", Symbol::Concat),
+            ("...", Symbol::Ellipsis),
+            (":", Symbol::Colon),
+            ("::", Symbol::DoubleColon),
+        ];
+        for (operator, symbol) in cases {
+            let source = format!("a{operator}");
+            let tokens = lex_with(&source, options.clone()).token_list;
+            assert_eq!(tokens[1].value, TokenValue::Symbol(symbol), "{source:?}");
+            assert_eq!(tokens[1].span, Span { start: 1, end: source.len() });
+            assert_eq!(tokens[2].value, TokenValue::EOF);
+        }
+    }
+
+    #[test]
+    fn question_mark_hints_and_recovers() {
+        assert_eq!(lex_error("a ? b : c"), LexErrorKind::TernaryOperator);
+        assert!(LexErrorKind::TernaryOperator.to_string().contains("use 'a and b or c'"));
+        let (tokens, diagnostics) = lex_recover("a ? b");
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(diagnostics[0].code, LexErrorKind::TernaryOperator);
+    }
+
+    #[test]
+    fn double_colon_is_one_symbol_from_lua52() {
+        let lua51 = LexerOptions { version: LuaVersion::Lua51, ..LexerOptions::default() };
+        let lua52 = LexerOptions { version: LuaVersion::Lua52, ..LexerOptions::default() };
+        assert_eq!(
+            lex_with("f:::g ::top:: obj::method(x)", lua52).lexemes(),
+            tok![
+                name "f", sym "::", :, name "g", sym "::", name "top", sym "::", name "obj", sym "::", name "method",
+                sym "(", name "x", sym ")",
+            ]
+        );
+        assert_eq!(
+            lex_with("f:::g ::top::", lua51).lexemes(),
+            tok![name "f", :, :, :, name "g", :, :, name "top", :, :]
+        );
+    }
+
+    #[test]
+    fn comment_marker_at_end_of_input() {
+        assert_eq!(values("x --"), [TokenValue::Name("x".to_string())]);
+        assert_eq!(values("--"), []);
+        let options = LexerOptions { keep_comments: true, ..LexerOptions::default() };
+        let tokens = lex_with("x --", options.clone()).token_list;
+        assert_eq!(tokens[1].value, TokenValue::Comment(CommentKind::Line, String::new()));
+        assert_eq!(tokens[1].span, Span { start: 2, end: 4 });
+        assert_eq!(tokens[2].value, TokenValue::EOF);
+        let tokens = lex_with("--[", options).token_list;
+        assert_eq!(tokens[0].value, TokenValue::Comment(CommentKind::Line, "[".to_string()));
+        assert_eq!(tokens[1].value, TokenValue::EOF);
+    }
+
+    #[test]
+    fn token_at_offset_finds_the_covering_token() {
+        let lexer = lex("ab = 12");
+        assert_eq!(lexer.token_at_offset(1).map(|(index, _)| index), Some(0));
+        assert_eq!(lexer.token_at_offset(2), None);
+        assert_eq!(lexer.token_at_offset(3).map(|(index, _)| index), Some(1));
+        assert_eq!(lexer.token_at_offset(6).unwrap().1.value, integer(12, "12"));
+        assert_eq!(lexer.token_at_offset(7), None);
+    }
+
+    #[test]
+    fn leading_zeros_are_decimal() {
+        assert_eq!(
+            values("010 007.5 00.5"),
+            [
+                integer(10, "010"),
+                number(NumberValue::Float(7.5), "007.5"),
+                number(NumberValue::Float(0.5), "00.5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexes_a_local_assignment() {
+        assert_eq!(lex("local x = 1\n").lexemes(), tok![local, name "x", =, num 1, eol]);
+    }
+
+    #[test]
+    fn lexes_multi_char_symbols_and_strings() {
+        assert_eq!(
+            lex("if a ~= 'b' then return ... end").lexemes(),
+            tok![if, name "a", sym "~=", str 'b', then, return, sym "...", end]
+        );
+    }
+
+    #[test]
+    fn hex_mantissa_needs_a_digit() {
+        assert_eq!(values("0x.1p4 0x1.")[0], number(NumberValue::Float(1.0), "0x.1p4"));
+        assert_eq!(values("0x1.")[0], number(NumberValue::Float(1.0), "0x1."));
+        assert_eq!(lex_error("0x."), LexErrorKind::MalformedNumber);
+        assert_eq!(lex_error("0xp1"), LexErrorKind::MalformedNumber);
+        assert_eq!(lex_error("0x"), LexErrorKind::MalformedNumber);
+    }
+
+    #[test]
+    fn strict_lexing_fails_fast_and_recovery_collects() {
+        assert_eq!(lex_strict("a @ b ?").unwrap_err().kind, LexErrorKind::UnexpectedChar('@'));
+        let (tokens, diagnostics) = lex_recover("a @ b ?");
+        let codes: Vec<LexErrorKind> = diagnostics.into_iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(codes, [LexErrorKind::UnexpectedChar('@'), LexErrorKind::TernaryOperator]);
+        assert_eq!(tokens.len(), 3);
+        let (tokens, diagnostics) = lex_recover("a [[");
+        assert_eq!(diagnostics[0].code, LexErrorKind::UnterminatedLongBracket);
+        assert_eq!(tokens.len(), 1);
+    }
+
+    #[test]
+    fn span_helpers() {
+        let a = Span { start: 2, end: 4 };
+        let b = Span { start: 6, end: 9 };
+        assert_eq!(a.join(b), Span { start: 2, end: 9 });
+        assert_eq!(b.join(a), Span { start: 2, end: 9 });
+        assert!(a.contains(2) && a.contains(3) && !a.contains(4));
+        assert_eq!(b.len(), 3);
+        assert!(!b.is_empty());
+        assert!(Span { start: 5, end: 5 }.is_empty());
+        assert_eq!(lex("'ü'").token_list[0].span.len(), 4);
+    }
+
+    #[test]
+    fn line_endings_record_lf_and_crlf() {
+        let options = LexerOptions { collect_line_endings: true, ..LexerOptions::default() };
+        let lexer = lex_with("a\r\nb\nc\rd\n", options);
+        assert_eq!(lexer.line_ending(1), Some(LineEnding::CrLf));
+        assert_eq!(lexer.line_ending(2), Some(LineEnding::Lf));
+        assert_eq!(lexer.line_ending(3), Some(LineEnding::Lf));
+        assert_eq!(lexer.line_ending(4), None);
+        assert_eq!(lexer.lexemes(), tok![name "a", eol, name "b", eol, name "c", name "d", eol]);
+    }
+
+    #[test]
+    fn trailing_whitespace_is_collected_before_line_breaks() {
+        let options = LexerOptions { collect_trailing_whitespace: true, ..LexerOptions::default() };
+        let lexer = lex_with("a  \nb\t\r\nc\n  \n", options);
+        assert_eq!(
+            lexer.trailing_whitespace,
+            [
+                (Position { line: 1, column: 2 }, Span { start: 1, end: 3 }),
+                (Position { line: 2, column: 2 }, Span { start: 5, end: 6 }),
+                (Position { line: 4, column: 1 }, Span { start: 10, end: 12 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn integers_only_rejects_floats() {
+        let options = LexerOptions { integers_only: true, ..LexerOptions::default() };
+        assert_eq!(lex_with("x = 10 + 0x1F", options.clone()).token_list.len(), 6);
+        for source in ["1.5", ".5", "1e3", "0x1p4"] {
+            let error = Lexer::with_options(source.to_string(), options.clone()).scan_tokens().unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::FloatNotAllowed, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn columns_are_right_on_long_lines() {
+        let source = format!("{}x", "a ".repeat(5000));
+        let tokens = lex(&source).token_list;
+        assert_eq!(tokens[5000].position, Position { line: 1, column: 10001 });
+        assert_eq!(tokens[5000].span, Span { start: 10000, end: 10001 });
+    }
+
+    #[test]
+    fn typed_number_accessors() {
+        let tokens = lex("42 4.5 0x10 x").token_list;
+        assert_eq!(tokens[0].as_i64(), Some(42));
+        assert_eq!(tokens[1].as_i64(), None);
+        assert_eq!(tokens[1].as_f64(), Some(4.5));
+        assert_eq!(tokens[2].as_i64(), Some(16));
+        assert_eq!(tokens[3].as_i64(), None);
+        assert_eq!(tokens[3].as_f64(), None);
+        // Before 5.3 the value is a float, but an integral lexeme still reads back exactly.
+        let lua51 = LexerOptions { version: LuaVersion::Lua51, ..LexerOptions::default() };
+        let tokens = lex_with("9007199254740993", lua51).token_list;
+        assert_eq!(tokens[0].as_i64(), Some(9007199254740993));
+        assert_eq!(tokens[0].as_f64(), Some(9007199254740992.0));
+    }
+
+    #[test]
+    fn long_brackets_store_line_breaks_as_lf() {
+        assert_eq!(values("[[\r\na\r\nb\rc\n\rd]]"), [TokenValue::String("a\nb\nc\nd".to_string())]);
+        assert_eq!(values("[==[a]]b]==]"), [TokenValue::String("a]]b".to_string())]);
+    }
+
+    #[test]
+    fn debug_table_aligns_columns() {
+        assert_eq!(
+            lex("x = 'a\\n'\n").debug_table(),
+            "KIND   | LEXEME | LINE:COL\n\
+             Name   | x      | 1:1\n\
+             Symbol | =      | 1:3\n\
+             String | 'a\\n'  | 1:5\n\
+             EOL    | \\n     | 1:10\n\
+             EOF    |        | 2:1\n"
+        );
+    }
+
+    #[test]
+    fn max_steps_bounds_the_scan() {
+        let options = LexerOptions { max_steps: Some(3), ..LexerOptions::default() };
+        assert_eq!(lex_with("a b", options.clone()).token_list.len(), 3);
+        let error = Lexer::with_options("a b c".to_string(), options).scan_tokens().unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::StepLimitExceeded);
+        assert_eq!(error.position, Position { line: 1, column: 6 });
+    }
+
+    #[test]
+    fn name_classifier_overrides_names() {
+        fn classify(word: &str) -> Option<TokenValue> {
+            word.chars().all(|ch| ch.is_ascii_uppercase()).then(|| TokenValue::CustomReserved(word.to_string()))
+        }
+        let options = LexerOptions { name_classifier: Some(classify), ..LexerOptions::default() };
+        let tokens = lex_with("SELECT x end", options).token_list;
+        assert_eq!(tokens[0].value, TokenValue::CustomReserved("SELECT".to_string()));
+        assert_eq!(tokens[1].value, TokenValue::Name("x".to_string()));
+        assert_eq!(tokens[2].value, TokenValue::Reserved(ReservedWord::End));
+    }
+
+    #[test]
+    fn diagnostics_cover_the_bad_input() {
+        let (_, diagnostics) = lex_recover("x = 'open\ny");
+        assert_eq!(
+            diagnostics,
+            [Diagnostic {
+                range: (Position { line: 1, column: 5 }, Position { line: 1, column: 10 }),
+                severity: Severity::Error,
+                message: "Unfinished string.".to_string(),
+                code: LexErrorKind::UnterminatedString,
+            }]
+        );
+    }
+
+    #[test]
+    fn signed_exponents_stay_in_the_number() {
+        assert_eq!(lex("1e+5 + 2E-3-1e5").lexemes(), tok![num 1e+5, +, num 2E-3, -, num 1e5]);
+        assert_eq!(lex_error("1e+"), LexErrorKind::MalformedNumber);
+        assert_eq!(lex_error("1e-"), LexErrorKind::MalformedNumber);
+    }
+
+    #[test]
+    fn tokenizer_trait_drives_any_lexer() {
+        fn names(tokenizer: &mut impl Tokenizer) -> Vec<String> {
+            let mut names = vec![];
+            while let Ok(token) = tokenizer.next_token() {
+                match token.value {
+                    TokenValue::Name(name) => names.push(name),
+                    TokenValue::EOF => break,
+                    _ => {}
+                }
+            }
+            names
+        }
+        let mut lexer = Lexer::new("a = b\nc".to_string());
+        assert_eq!(Tokenizer::peek_token(&mut lexer).unwrap().value, TokenValue::Name("a".to_string()));
+        assert_eq!(Tokenizer::position(&lexer), Position { line: 1, column: 1 });
+        assert_eq!(names(&mut lexer), ["a", "b", "c"]);
+        assert_eq!(Tokenizer::position(&lexer), Position { line: 2, column: 2 });
+    }
+
+    #[test]
+    fn tokens_on_current_line_resets_at_eol() {
+        let mut lexer = Lexer::new("a = 1 -- c\nb".to_string());
+        let mut counts = vec![];
+        loop {
+            let token = lexer.next_token().unwrap();
+            counts.push(lexer.tokens_on_current_line());
+            if matches!(token.value, TokenValue::EOF) {
+                break;
+            }
+        }
+        assert_eq!(counts, [1, 2, 3, 0, 1, 1]);
+    }
+
+    #[test]
+    fn keyword_prefixes_are_names() {
+        assert_eq!(
+            lex("andy ended returnx _end END end").lexemes(),
+            tok![name "andy", name "ended", name "returnx", name "_end", name "END", end]
+        );
+    }
+
+    #[test]
+    fn single_char_escapes_decode() {
+        assert_eq!(
+            values(r#""\a\b\f\n\r\t\v\\\"\'""#),
+            [TokenValue::String("\x07\x08\x0C\n\r\t\x0B\\\"'".to_string())]
+        );
+        assert_eq!(lex_error(r#""\q""#), LexErrorKind::InvalidEscape);
+        assert_eq!(lex("x").token_list[0].string_value(), None);
+    }
+
+    #[test]
+    fn length_limits_reject_long_tokens() {
+        let options = LexerOptions { max_name_length: Some(3), max_number_length: Some(4), ..LexerOptions::default() };
+        assert_eq!(lex_with("abc 1234", options.clone()).token_list.len(), 3);
+        for source in ["abcd", "12345", "1.2e5"] {
+            let error = Lexer::with_options(source.to_string(), options.clone()).scan_tokens().unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::TokenTooLong, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn lenient_escapes_keep_the_backslash() {
+        let options = LexerOptions { lenient_escapes: true, ..LexerOptions::default() };
+        let lexer = lex_with(r#""\q\d\n""#, options);
+        assert_eq!(lexer.token_list[0].value, TokenValue::String("\\q\\d\n".to_string()));
+    }
+
+    #[test]
+    fn exact_numbers_keep_only_the_lexeme() {
+        let options = LexerOptions { exact_numbers: true, ..LexerOptions::default() };
+        let lexer = lex_with("123456789012345678901234567890 0x1p-3 .5", options.clone());
+        assert_eq!(lexer.token_list[0].value, number(NumberValue::Unparsed, "123456789012345678901234567890"));
+        assert_eq!(lexer.token_list[1].value, number(NumberValue::Unparsed, "0x1p-3"));
+        assert_eq!(lexer.token_list[2].value, number(NumberValue::Unparsed, ".5"));
+        assert_eq!(lexer.token_list[0].as_f64(), None);
+        for source in ["1e", "0x", "0x.p1"] {
+            let error = Lexer::with_options(source.to_string(), options.clone()).scan_tokens().unwrap_err();
+            assert_eq!(error.kind, LexErrorKind::MalformedNumber, "{source:?}");
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::analysis::BlockTracker;
+use crate::{LexError, LexErrorKind, Lexer, ReservedWord, Symbol, Token, TokenValue};
+
+#[derive(Debug, PartialEq)]
+pub enum ReplStatus {
+    Complete,
+    NeedMore,
+    Error(LexError),
+}
+
+#[derive(Default)]
+pub struct ReplLexer {
+    buffer: String,
+}
+
+impl ReplLexer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_line(&mut self, line: &str) -> ReplStatus {
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        let mut lexer = Lexer::new(self.buffer.clone());
+        match lexer.scan_tokens() {
+            Ok(()) if is_incomplete(&lexer.token_list) => ReplStatus::NeedMore,
+            Ok(()) => ReplStatus::Complete,
+            // Only long brackets may span lines; a short string cut by the newline stays an error.
+            Err(error)
+                if matches!(error.kind, LexErrorKind::UnterminatedLongBracket | LexErrorKind::UnterminatedLongComment) =>
+            {
+                ReplStatus::NeedMore
+            }
+            Err(error) => ReplStatus::Error(error),
+        }
+    }
+
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+
+    pub fn take(&mut self) -> String {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+fn is_incomplete(tokens: &[Token]) -> bool {
+    let mut blocks = BlockTracker::default();
+    let mut brackets = 0isize;
+    let mut last = None;
+    for token in tokens {
+        blocks.update(&token.value);
+        match &token.value {
+            TokenValue::Symbol(Symbol::LeftParen | Symbol::LeftBrace | Symbol::LeftBracket) => brackets += 1,
+            TokenValue::Symbol(Symbol::RightParen | Symbol::RightBrace | Symbol::RightBracket) => brackets -= 1,
+            _ => {}
+        }
+        if !matches!(token.value, TokenValue::EOL | TokenValue::EOF) {
+            last = Some(&token.value);
+        }
+    }
+    blocks.depth > 0 || brackets > 0 || last.is_some_and(expects_more)
+}
+
+fn expects_more(value: &TokenValue) -> bool {
+    match value {
+        TokenValue::Symbol(symbol) => !matches!(
+            symbol,
+            Symbol::RightParen
+                | Symbol::RightBrace
+                | Symbol::RightBracket
+                | Symbol::Semicolon
+                | Symbol::Ellipsis
+                | Symbol::DoubleColon
+        ),
+        TokenValue::Reserved(reserved) => matches!(
+            reserved,
+            ReservedWord::And | ReservedWord::Or | ReservedWord::Not | ReservedWord::Local | ReservedWord::In
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_line_waits_for_complete_input() {
+        let mut repl = ReplLexer::new();
+        assert_eq!(repl.push_line("function f(x)"), ReplStatus::NeedMore);
+        assert_eq!(repl.push_line("  return x +"), ReplStatus::NeedMore);
+        assert_eq!(repl.push_line("    1"), ReplStatus::NeedMore);
+        assert_eq!(repl.push_line("end"), ReplStatus::Complete);
+        assert_eq!(repl.take(), "function f(x)\n  return x +\n    1\nend\n");
+        assert_eq!(repl.buffer(), "");
+    }
+
+    #[test]
+    fn push_line_continues_brackets_and_long_strings() {
+        let mut repl = ReplLexer::new();
+        assert_eq!(repl.push_line("t = {1,"), ReplStatus::NeedMore);
+        assert_eq!(repl.push_line("2}"), ReplStatus::Complete);
+        repl.take();
+        assert_eq!(repl.push_line("s = [["), ReplStatus::NeedMore);
+        assert_eq!(repl.push_line("--[=["), ReplStatus::NeedMore);
+        assert_eq!(repl.push_line("]]"), ReplStatus::Complete);
+        repl.take();
+        assert_eq!(repl.push_line("x = 1;"), ReplStatus::Complete);
+        repl.take();
+        // Without the lua53 feature the default is Lua 5.1, where `::` is two colons and the last one waits for a name.
+        #[cfg(feature = "lua53")]
+        assert_eq!(repl.push_line("::top::"), ReplStatus::Complete);
+    }
+
+    #[test]
+    fn push_line_reports_errors() {
+        let mut repl = ReplLexer::new();
+        let ReplStatus::Error(error) = repl.push_line("s = 'open") else { panic!() };
+        assert_eq!(error.kind, LexErrorKind::UnterminatedString);
+    }
+}
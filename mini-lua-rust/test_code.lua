@@ -5,3 +5,28 @@ if 1 then
 y=x
 end
 print (y)
+z = 0xFF + 3.14 + 1e5 + 0x1p4 + 42
+ok = a == b and c ~= d or e <= f and g >= h
+function v(...) return ... end
+s = 1
+..x
+n = 1+2-3*4/5%6^7
+b = 1<2 == 1>2, 1 ..2
+c = a.. ...
+p =
+p <
+p >
+p .
+p ..
+p :
+p ...
+a:b c.d:e
+q = 1 --
+--
+--[
+z = 010 + 007 + 00.5 + 0 + 007.5
+h = 0x.1p0 + 0x1.p0 + 0x.8 + 0xA.
+e = 1e+5 + 2E-3-1e5
+andy = ended or returnx and locale or function2 or inx or nil_ or _end or END
+s = "a\tb\r\a\b\f\v"
+obj:method(x) a: :b
@@ -0,0 +1,36 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mini-lua-rust"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn json_output_matches_fixture() {
+    let output = run(&["--json"], include_str!("../test_code.lua"));
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), include_str!("../test_code.jsonl"));
+}
+
+#[test]
+fn token_output_matches_fixture() {
+    let output = run(&[], include_str!("../test_code.lua"));
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), include_str!("../test_code.out"));
+}
+
+#[test]
+fn lex_error_exits_with_rendered_message() {
+    let output = run(&["--json"], "x = 'open\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "1:5: Unfinished string.\n1 | x = 'open\n  |     ^\n2 | \n");
+}